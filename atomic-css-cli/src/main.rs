@@ -31,21 +31,211 @@
 // }
 //
 
+use atomic_css_organism::importer::import_css;
+use atomic_css_organism::lint::{ContrastLevel, SpecificityBudget};
+use atomic_css_organism::organism::Organism;
+use atomic_css_organism::serializer::Format;
+use atomic_css_organism::source_map::*;
 use atomic_css_runtime::Runtime;
 use std::env;
 use std::fs;
+use std::path::Path;
+use std::process;
 
 fn main() {
-    let filename = env::args().nth(1).expect("Expected file argument");
-    let src = fs::read_to_string(filename).expect("Failed to read file");
+    let mut filename = None;
+    let mut contrast_level = None;
+    let mut tokens_filename = None;
+    let mut export_tokens_filename = None;
+    let mut export_props_filename = None;
+    let mut out_dir = None;
+    let mut check_conflicts = false;
+    let mut flatten = false;
+    let mut format = Format::default();
+    let mut source_map = false;
+    let mut specificity_budget = None;
+    let mut import_filename = None;
+
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--contrast" => {
+                let level = args.next().expect("Expected contrast level (aa or aaa)");
+                contrast_level = Some(
+                    level
+                        .parse::<ContrastLevel>()
+                        .expect("Invalid contrast level"),
+                );
+            }
+            "--conflicts" => check_conflicts = true,
+            "--flatten" => flatten = true,
+            "--source-map" => source_map = true,
+            "--format" => {
+                let name = args
+                    .next()
+                    .expect("Expected format (compact, pretty or minified)");
+                format = name.parse::<Format>().expect("Invalid format");
+            }
+            "--specificity" => {
+                specificity_budget.get_or_insert_with(SpecificityBudget::default);
+            }
+            "--max-specificity" => {
+                let max = args.next().expect("Expected specificity (e.g. 0,3,0)");
+                specificity_budget
+                    .get_or_insert_with(SpecificityBudget::default)
+                    .max_specificity = max.parse().expect("Invalid specificity");
+            }
+            "--max-descendants" => {
+                let max = args.next().expect("Expected descendant depth");
+                specificity_budget
+                    .get_or_insert_with(SpecificityBudget::default)
+                    .max_descendant_depth = max.parse().expect("Invalid descendant depth");
+            }
+            "--tokens" => tokens_filename = Some(args.next().expect("Expected tokens file")),
+            "--export-tokens" => {
+                export_tokens_filename = Some(args.next().expect("Expected tokens output file"))
+            }
+            "--export-props" => {
+                export_props_filename = Some(args.next().expect("Expected props output file"))
+            }
+            "--import-css" => import_filename = Some(args.next().expect("Expected css file")),
+            "--out-dir" => out_dir = Some(args.next().expect("Expected output directory")),
+            _ => filename = Some(arg),
+        }
+    }
+
+    // prints the DSL source of an existing stylesheet, e.g. to migrate it
+    if let Some(import_filename) = import_filename {
+        let css = fs::read_to_string(&import_filename).expect("Failed to read css file");
+        let molecule_name = Path::new(&import_filename)
+            .file_stem()
+            .and_then(|stem| stem.to_str())
+            .unwrap_or("imported");
+        match import_css(&css) {
+            Ok(import) => print!("{}", import.get_dsl(molecule_name)),
+            Err(error) => {
+                eprintln!("error: {}", error);
+                process::exit(1);
+            }
+        }
+        return;
+    }
+
+    let filename = filename.expect("Expected file argument");
+    let src = fs::read_to_string(&filename).expect("Failed to read file");
 
     let mut runtime = Runtime::new();
-    runtime.run(&src).expect("Failed to run program");
+
+    if let Some(tokens_filename) = tokens_filename {
+        let tokens = fs::read_to_string(tokens_filename).expect("Failed to read tokens file");
+        runtime
+            .organism
+            .import_dtcg(&tokens)
+            .expect("Failed to import tokens");
+    }
+
+    if let Err(error) = runtime.run(&src) {
+        eprintln!("error: {}", error);
+        process::exit(1);
+    }
 
     runtime
         .organism
         .update_exports()
         .expect("Failed to update exports");
 
-    println!("{:?}", &runtime.organism);
+    // without css nesting for older browsers when flattened
+    let get_css =
+        |organism: &Organism| organism.serialize(format.get_serializer().as_ref(), flatten);
+
+    if let Some(out_dir) = out_dir {
+        let stem = Path::new(&filename)
+            .file_stem()
+            .expect("Expected file name")
+            .to_string_lossy()
+            .to_string();
+        let out_dir = Path::new(&out_dir);
+        fs::create_dir_all(out_dir).expect("Failed to create output directory");
+
+        // the source map of a stylesheet is written next to it
+        let write_stylesheet = |organism: &Organism, name: String| {
+            let css = match source_map {
+                true => {
+                    let output = organism.write(format.get_serializer().as_ref(), flatten);
+                    let map_name = format!("{}.map", name);
+                    fs::write(
+                        out_dir.join(&map_name),
+                        get_source_map(&output, &name, &filename, &src),
+                    )
+                    .expect("Failed to write source map");
+                    format!("{}\n{}\n", output.css, get_source_map_comment(&map_name))
+                }
+                false => get_css(organism),
+            };
+            fs::write(out_dir.join(name), css).expect("Failed to write stylesheet");
+        };
+
+        write_stylesheet(&runtime.organism, format!("{}.css", stem));
+
+        // one stylesheet per overlay, sharing the class names of the base one
+        for overlay in &runtime.organism.overlays {
+            let organism = runtime
+                .organism
+                .apply_overlay(overlay)
+                .expect("Failed to apply overlay");
+            write_stylesheet(&organism, format!("{}.{}.css", stem, overlay.name));
+        }
+    } else {
+        if source_map {
+            eprintln!("error: --source-map requires --out-dir");
+            process::exit(1);
+        }
+        print!("{}", get_css(&runtime.organism));
+    }
+
+    if let Some(export_tokens_filename) = export_tokens_filename {
+        let tokens = runtime
+            .organism
+            .export_dtcg()
+            .expect("Failed to export tokens");
+        fs::write(export_tokens_filename, tokens).expect("Failed to write tokens file");
+    }
+
+    if let Some(export_props_filename) = export_props_filename {
+        let props = runtime
+            .organism
+            .export_prop_tables()
+            .expect("Failed to export props");
+        fs::write(export_props_filename, props).expect("Failed to write props file");
+    }
+
+    let mut has_warnings = false;
+
+    if let Some(level) = contrast_level {
+        let issues = runtime.organism.check_contrast(level);
+        for issue in &issues {
+            eprintln!("warning: {}", issue);
+        }
+        has_warnings |= !issues.is_empty();
+    }
+
+    if check_conflicts {
+        let conflicts = runtime.organism.check_conflicts();
+        for conflict in &conflicts {
+            eprintln!("warning: {}", conflict);
+        }
+        has_warnings |= !conflicts.is_empty();
+    }
+
+    if let Some(budget) = specificity_budget {
+        let issues = runtime.organism.check_specificity(&budget);
+        for issue in &issues {
+            eprintln!("warning: {}", issue);
+        }
+        has_warnings |= !issues.is_empty();
+    }
+
+    if has_warnings {
+        process::exit(1);
+    }
 }
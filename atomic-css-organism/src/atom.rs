@@ -10,6 +10,8 @@ pub struct Atom {
     pub name: AtomName,
     pub electrons: Vec<ElectronName>,
     pub imports: Vec<(MoleculeName, AtomName)>,
+    /// Atoms of the same molecule whose classes this atom also applies.
+    pub extends: Vec<AtomName>,
 }
 
 impl Atom {
@@ -34,6 +36,13 @@ impl Atom {
         self
     }
 
+    pub fn with_extends(mut self, atoms: Vec<&str>) -> Self {
+        for atom in atoms {
+            self.insert_extends(atom);
+        }
+        self
+    }
+
     pub fn insert_electron(&mut self, electron: &str) {
         self.electrons.push(electron.to_string());
     }
@@ -41,4 +50,8 @@ impl Atom {
     pub fn insert_import(&mut self, molecule: &str, atom: &str) {
         self.imports.push((molecule.to_string(), atom.to_string()));
     }
+
+    pub fn insert_extends(&mut self, atom: &str) {
+        self.extends.push(atom.to_string());
+    }
 }
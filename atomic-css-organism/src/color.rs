@@ -0,0 +1,446 @@
+use std::fmt;
+
+/// An sRGB color with every channel, alpha included, in the `0.0..=1.0` range.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Color {
+    pub r: f64,
+    pub g: f64,
+    pub b: f64,
+    pub a: f64,
+}
+
+fn clamp(value: f64) -> f64 {
+    value.clamp(0.0, 1.0)
+}
+
+fn format_number(value: f64, decimals: i32) -> String {
+    let factor = 10f64.powi(decimals);
+    let rounded = (value * factor).round() / factor;
+    let formatted = format!("{:.*}", decimals as usize, rounded);
+    let trimmed = if formatted.contains('.') {
+        formatted.trim_end_matches('0').trim_end_matches('.')
+    } else {
+        &formatted
+    };
+    match trimmed {
+        "-0" => "0".to_string(),
+        _ => trimmed.to_string(),
+    }
+}
+
+/// Parses either a percentage (`10%`) or a plain fraction (`0.1`).
+pub fn parse_amount(amount: &str) -> Result<f64, String> {
+    let amount = amount.trim();
+    let parsed = match amount.strip_suffix('%') {
+        Some(percentage) => percentage.trim().parse::<f64>().map(|p| p / 100.0),
+        None => amount.parse::<f64>(),
+    };
+    match parsed {
+        Ok(value) if value.is_finite() => Ok(value),
+        _ => Err(format!("Invalid amount `{}`", amount)),
+    }
+}
+
+fn parse_hue(hue: &str) -> Result<f64, String> {
+    let hue = hue.trim();
+    let parsed = match hue.strip_suffix("deg") {
+        Some(degrees) => degrees.parse::<f64>(),
+        None => hue.parse::<f64>(),
+    };
+    match parsed {
+        Ok(value) if value.is_finite() => Ok(value.rem_euclid(360.0)),
+        _ => Err(format!("Invalid hue `{}`", hue)),
+    }
+}
+
+fn parse_channel(channel: &str, scale: f64) -> Result<f64, String> {
+    let channel = channel.trim();
+    match channel.strip_suffix('%') {
+        Some(_) => parse_amount(channel),
+        None => channel
+            .parse::<f64>()
+            .ok()
+            .filter(|value| value.is_finite())
+            .map(|value| value / scale)
+            .ok_or_else(|| format!("Invalid channel `{}`", channel)),
+    }
+}
+
+/// Splits the arguments of a color function, accepting both the legacy comma
+/// separated syntax and the modern space separated one with an optional
+/// `/ alpha` suffix.
+fn split_arguments(arguments: &str) -> (Vec<&str>, Option<&str>) {
+    let (channels, alpha) = match arguments.split_once('/') {
+        Some((channels, alpha)) => (channels, Some(alpha.trim())),
+        None => (arguments, None),
+    };
+    let mut channels: Vec<&str> = channels
+        .split(|c: char| c == ',' || c.is_whitespace())
+        .filter(|channel| !channel.is_empty())
+        .collect();
+    if alpha.is_none() && channels.len() == 4 {
+        let alpha = channels.pop();
+        return (channels, alpha);
+    }
+    (channels, alpha)
+}
+
+fn hue_to_rgb(p: f64, q: f64, t: f64) -> f64 {
+    let t = t.rem_euclid(1.0);
+    if t < 1.0 / 6.0 {
+        p + (q - p) * 6.0 * t
+    } else if t < 1.0 / 2.0 {
+        q
+    } else if t < 2.0 / 3.0 {
+        p + (q - p) * (2.0 / 3.0 - t) * 6.0
+    } else {
+        p
+    }
+}
+
+fn to_linear(channel: f64) -> f64 {
+    if channel <= 0.04045 {
+        channel / 12.92
+    } else {
+        ((channel + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+fn from_linear(channel: f64) -> f64 {
+    if channel <= 0.0031308 {
+        channel * 12.92
+    } else {
+        1.055 * channel.powf(1.0 / 2.4) - 0.055
+    }
+}
+
+impl Color {
+    pub fn new(r: f64, g: f64, b: f64, a: f64) -> Self {
+        Self {
+            r: clamp(r),
+            g: clamp(g),
+            b: clamp(b),
+            a: clamp(a),
+        }
+    }
+
+    /// Hue in degrees, saturation and lightness in the `0.0..=1.0` range.
+    pub fn from_hsl(h: f64, s: f64, l: f64, a: f64) -> Self {
+        let (s, l) = (clamp(s), clamp(l));
+        if s == 0.0 {
+            return Self::new(l, l, l, a);
+        }
+        let h = h.rem_euclid(360.0) / 360.0;
+        let q = if l < 0.5 {
+            l * (1.0 + s)
+        } else {
+            l + s - l * s
+        };
+        let p = 2.0 * l - q;
+        Self::new(
+            hue_to_rgb(p, q, h + 1.0 / 3.0),
+            hue_to_rgb(p, q, h),
+            hue_to_rgb(p, q, h - 1.0 / 3.0),
+            a,
+        )
+    }
+
+    /// Lightness in the `0.0..=1.0` range, hue in degrees.
+    pub fn from_oklch(l: f64, c: f64, h: f64, a: f64) -> Self {
+        let (sin, cos) = h.to_radians().sin_cos();
+        let (ok_a, ok_b) = (c * cos, c * sin);
+
+        let l_ = (l + 0.3963377774 * ok_a + 0.2158037573 * ok_b).powi(3);
+        let m_ = (l - 0.1055613458 * ok_a - 0.0638541728 * ok_b).powi(3);
+        let s_ = (l - 0.0894841775 * ok_a - 1.2914855480 * ok_b).powi(3);
+
+        Self::new(
+            from_linear(4.0767416621 * l_ - 3.3077115913 * m_ + 0.2309699292 * s_),
+            from_linear(-1.2684380046 * l_ + 2.6097574011 * m_ - 0.3413193965 * s_),
+            from_linear(-0.0041960863 * l_ - 0.7034186147 * m_ + 1.7076147010 * s_),
+            a,
+        )
+    }
+
+    pub fn parse(source: &str) -> Result<Self, String> {
+        let source = source.trim();
+        if let Some(hex) = source.strip_prefix('#') {
+            return Self::parse_hex(hex).ok_or_else(|| format!("Invalid hex color `{}`", source));
+        }
+
+        let Some((function, arguments)) = source
+            .strip_suffix(')')
+            .and_then(|source| source.split_once('('))
+        else {
+            return Err(format!("Unsupported color `{}`", source));
+        };
+
+        let (channels, alpha) = split_arguments(arguments);
+        let alpha = match alpha {
+            Some(alpha) => parse_amount(alpha)?,
+            None => 1.0,
+        };
+
+        match (function.trim(), channels.as_slice()) {
+            ("rgb" | "rgba", [r, g, b]) => Ok(Self::new(
+                parse_channel(r, 255.0)?,
+                parse_channel(g, 255.0)?,
+                parse_channel(b, 255.0)?,
+                alpha,
+            )),
+            ("hsl" | "hsla", [h, s, l]) => Ok(Self::from_hsl(
+                parse_hue(h)?,
+                parse_channel(s, 100.0)?,
+                parse_channel(l, 100.0)?,
+                alpha,
+            )),
+            ("oklch", [l, c, h]) => Ok(Self::from_oklch(
+                parse_channel(l, 1.0)?,
+                parse_channel(c, 1.0)?,
+                parse_hue(h)?,
+                alpha,
+            )),
+            _ => Err(format!("Unsupported color `{}`", source)),
+        }
+    }
+
+    fn parse_hex(hex: &str) -> Option<Self> {
+        if !hex.chars().all(|c| c.is_ascii_hexdigit()) {
+            return None;
+        }
+        let digits: Vec<u8> = match hex.len() {
+            3 | 4 => hex
+                .chars()
+                .map(|c| u8::from_str_radix(&format!("{}{}", c, c), 16).ok())
+                .collect::<Option<_>>()?,
+            6 | 8 => (0..hex.len())
+                .step_by(2)
+                .map(|i| u8::from_str_radix(&hex[i..i + 2], 16).ok())
+                .collect::<Option<_>>()?,
+            _ => return None,
+        };
+        let channel = |i: usize| digits.get(i).map_or(1.0, |d| *d as f64 / 255.0);
+        Some(Self::new(channel(0), channel(1), channel(2), channel(3)))
+    }
+
+    /// Hue in degrees, saturation and lightness in the `0.0..=1.0` range.
+    pub fn to_hsl_components(&self) -> (f64, f64, f64) {
+        let max = self.r.max(self.g).max(self.b);
+        let min = self.r.min(self.g).min(self.b);
+        let l = (max + min) / 2.0;
+        let delta = max - min;
+        if delta == 0.0 {
+            return (0.0, 0.0, l);
+        }
+        let s = delta / (1.0 - (2.0 * l - 1.0).abs());
+        let h = if max == self.r {
+            ((self.g - self.b) / delta).rem_euclid(6.0)
+        } else if max == self.g {
+            (self.b - self.r) / delta + 2.0
+        } else {
+            (self.r - self.g) / delta + 4.0
+        };
+        (h * 60.0, s, l)
+    }
+
+    /// Lightness in the `0.0..=1.0` range, hue in degrees.
+    pub fn to_oklch_components(&self) -> (f64, f64, f64) {
+        let (r, g, b) = (to_linear(self.r), to_linear(self.g), to_linear(self.b));
+
+        let l_ = (0.4122214708 * r + 0.5363325363 * g + 0.0514459929 * b).cbrt();
+        let m_ = (0.2119034982 * r + 0.6806995451 * g + 0.1073969566 * b).cbrt();
+        let s_ = (0.0883024619 * r + 0.2817188376 * g + 0.6299787005 * b).cbrt();
+
+        let l = 0.2104542553 * l_ + 0.7936177850 * m_ - 0.0040720468 * s_;
+        let ok_a = 1.9779984951 * l_ - 2.4285922050 * m_ + 0.4505937099 * s_;
+        let ok_b = 0.0259040371 * l_ + 0.7827717662 * m_ - 0.8086757660 * s_;
+
+        let c = (ok_a * ok_a + ok_b * ok_b).sqrt();
+        let h = if c < 1e-6 {
+            0.0
+        } else {
+            ok_b.atan2(ok_a).to_degrees().rem_euclid(360.0)
+        };
+        (l, c, h)
+    }
+
+    fn alpha_suffix(&self) -> String {
+        if self.a < 1.0 {
+            format!(" / {}", format_number(self.a, 3))
+        } else {
+            String::new()
+        }
+    }
+
+    pub fn to_hex(&self) -> String {
+        let byte = |channel: f64| (channel * 255.0).round() as u8;
+        let mut hex = format!(
+            "#{:02x}{:02x}{:02x}",
+            byte(self.r),
+            byte(self.g),
+            byte(self.b)
+        );
+        if byte(self.a) < 255 {
+            hex.push_str(&format!("{:02x}", byte(self.a)));
+        }
+        hex
+    }
+
+    pub fn to_rgb(&self) -> String {
+        format!(
+            "rgb({} {} {}{})",
+            format_number(self.r * 255.0, 0),
+            format_number(self.g * 255.0, 0),
+            format_number(self.b * 255.0, 0),
+            self.alpha_suffix()
+        )
+    }
+
+    pub fn to_hsl(&self) -> String {
+        let (h, s, l) = self.to_hsl_components();
+        format!(
+            "hsl({} {}% {}%{})",
+            format_number(h, 1),
+            format_number(s * 100.0, 1),
+            format_number(l * 100.0, 1),
+            self.alpha_suffix()
+        )
+    }
+
+    pub fn to_oklch(&self) -> String {
+        let (l, c, h) = self.to_oklch_components();
+        format!(
+            "oklch({}% {} {}{})",
+            format_number(l * 100.0, 2),
+            format_number(c, 4),
+            format_number(h, 2),
+            self.alpha_suffix()
+        )
+    }
+
+    /// Increases the HSL lightness by `amount`, e.g. `0.1` for `10%`.
+    pub fn lighten(&self, amount: f64) -> Self {
+        let (h, s, l) = self.to_hsl_components();
+        Self::from_hsl(h, s, l + amount, self.a)
+    }
+
+    /// Decreases the HSL lightness by `amount`, e.g. `0.1` for `10%`.
+    pub fn darken(&self, amount: f64) -> Self {
+        self.lighten(-amount)
+    }
+
+    /// Mixes two colors, `weight` being the proportion of `self` in the result.
+    pub fn mix(&self, other: &Color, weight: f64) -> Self {
+        let weight = clamp(weight);
+        let channel = |a: f64, b: f64| a * weight + b * (1.0 - weight);
+        Self::new(
+            channel(self.r, other.r),
+            channel(self.g, other.g),
+            channel(self.b, other.b),
+            channel(self.a, other.a),
+        )
+    }
+
+    pub fn with_alpha(&self, alpha: f64) -> Self {
+        Self::new(self.r, self.g, self.b, alpha)
+    }
+
+    /// Relative luminance as defined by WCAG 2.x.
+    pub fn relative_luminance(&self) -> f64 {
+        0.2126 * to_linear(self.r) + 0.7152 * to_linear(self.g) + 0.0722 * to_linear(self.b)
+    }
+
+    /// WCAG 2.x contrast ratio, from `1.0` (no contrast) to `21.0`.
+    pub fn contrast_ratio(&self, other: &Color) -> f64 {
+        let (a, b) = (self.relative_luminance(), other.relative_luminance());
+        (a.max(b) + 0.05) / (a.min(b) + 0.05)
+    }
+}
+
+impl fmt::Display for Color {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.to_hex())
+    }
+}
+
+/// Formats a contrast ratio the way it is reported by WCAG tools, e.g. `4.5`.
+pub fn format_ratio(ratio: f64) -> String {
+    format_number(ratio, 2)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_hex() {
+        assert_eq!(Color::parse("#ff0000"), Ok(Color::new(1.0, 0.0, 0.0, 1.0)));
+        assert_eq!(Color::parse("#f00"), Ok(Color::new(1.0, 0.0, 0.0, 1.0)));
+        assert_eq!(Color::parse("#ff000000").map(|c| c.a), Ok(0.0));
+        assert!(Color::parse("#ff00").is_ok());
+        assert!(Color::parse("#ff00g0").is_err());
+        assert!(Color::parse("#ff0").is_ok());
+        assert!(Color::parse("red").is_err());
+    }
+
+    #[test]
+    fn test_parse_functions() {
+        let red = Color::new(1.0, 0.0, 0.0, 1.0);
+        assert_eq!(Color::parse("rgb(255, 0, 0)"), Ok(red));
+        assert_eq!(Color::parse("rgb(100% 0% 0%)"), Ok(red));
+        assert_eq!(Color::parse("hsl(0, 100%, 50%)"), Ok(red));
+        assert_eq!(Color::parse("hsl(360deg 100% 50%)"), Ok(red));
+        assert_eq!(Color::parse("rgba(255, 0, 0, 0.5)").map(|c| c.a), Ok(0.5));
+        assert_eq!(Color::parse("rgb(255 0 0 / 50%)").map(|c| c.a), Ok(0.5));
+        assert_eq!(
+            Color::parse("oklch(62.8% 0.2577 29.23)").map(|c| c.to_hex()),
+            Ok("#ff0000".to_string())
+        );
+        assert!(Color::parse("rgb(255, 0)").is_err());
+        assert!(Color::parse("lab(50% 40 59)").is_err());
+    }
+
+    #[test]
+    fn test_conversions() {
+        let red = Color::parse("#ff0000").unwrap();
+        assert_eq!(red.to_hex(), "#ff0000");
+        assert_eq!(red.to_rgb(), "rgb(255 0 0)");
+        assert_eq!(red.to_hsl(), "hsl(0 100% 50%)");
+        assert_eq!(red.to_oklch(), "oklch(62.8% 0.2577 29.23)");
+        assert_eq!(red.with_alpha(0.4).to_hex(), "#ff000066");
+        assert_eq!(red.with_alpha(0.4).to_rgb(), "rgb(255 0 0 / 0.4)");
+    }
+
+    #[test]
+    fn test_lighten_darken() {
+        let red = Color::parse("#ff0000").unwrap();
+        assert_eq!(red.darken(0.1).to_hex(), "#cc0000");
+        assert_eq!(red.lighten(0.1).to_hex(), "#ff3333");
+        assert_eq!(red.lighten(1.0).to_hex(), "#ffffff");
+    }
+
+    #[test]
+    fn test_mix() {
+        let red = Color::parse("#ff0000").unwrap();
+        let blue = Color::parse("#0000ff").unwrap();
+        assert_eq!(red.mix(&blue, 0.5).to_hex(), "#800080");
+        assert_eq!(red.mix(&blue, 1.0).to_hex(), "#ff0000");
+    }
+
+    #[test]
+    fn test_contrast_ratio() {
+        let black = Color::parse("#000").unwrap();
+        let white = Color::parse("#fff").unwrap();
+        assert_eq!(format_ratio(black.contrast_ratio(&white)), "21");
+        assert_eq!(format_ratio(white.contrast_ratio(&white)), "1");
+        let gray = Color::parse("#767676").unwrap();
+        assert_eq!(format_ratio(gray.contrast_ratio(&white)), "4.54");
+    }
+
+    #[test]
+    fn test_parse_amount() {
+        assert_eq!(parse_amount("10%"), Ok(0.1));
+        assert_eq!(parse_amount("0.25"), Ok(0.25));
+        assert!(parse_amount("ten").is_err());
+    }
+}
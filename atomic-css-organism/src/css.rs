@@ -1,10 +1,20 @@
 use derive_more::Display;
+use std::ops::Range;
+
+use crate::selector::*;
+use crate::serializer::*;
+use crate::visitor::*;
+
+/// Byte range of a node in the source it was declared in, e.g. for source
+/// maps.
+pub type Span = Range<usize>;
 
 #[derive(Clone, Eq, PartialEq, Debug, Default, Display)]
 #[display(fmt = "#CSSDeclaration({}: {})", property, value)]
 pub struct CSSDeclaration {
     pub property: String,
     pub value: String,
+    pub span: Option<Span>,
 }
 
 impl CSSDeclaration {
@@ -12,8 +22,14 @@ impl CSSDeclaration {
         Self {
             property: property.to_string(),
             value: value.to_string(),
+            ..Default::default()
         }
     }
+
+    pub fn with_span(mut self, span: Span) -> Self {
+        self.span = Some(span);
+        self
+    }
 }
 
 #[derive(Clone, Eq, PartialEq, Debug, Display)]
@@ -23,11 +39,108 @@ pub enum CSSNode {
     CSSDeclaration(CSSDeclaration),
 }
 
+impl CSSNode {
+    pub fn get_css(&self) -> String {
+        CompactSerializer.serialize_node(self, 0)
+    }
+
+    pub fn get_span(&self) -> Option<&Span> {
+        match self {
+            CSSNode::CSSRule(rule) => rule.span.as_ref(),
+            CSSNode::CSSAtRule(at_rule) => at_rule.span.as_ref(),
+            CSSNode::CSSDeclaration(declaration) => declaration.span.as_ref(),
+        }
+    }
+
+    /// Copy of the node with every selector, at-rule parameter, property and
+    /// value passed through `f`.
+    pub fn map_text(&self, f: &impl Fn(&str) -> String) -> CSSNode {
+        struct TextMapper<F>(F);
+
+        impl<F: Fn(&str) -> String> VisitorMut for TextMapper<F> {
+            fn visit_rule_mut(&mut self, rule: &mut CSSRule) {
+                rule.selector = (self.0)(&rule.selector);
+                walk_rule_mut(self, rule);
+            }
+
+            fn visit_at_rule_mut(&mut self, at_rule: &mut CSSAtRule) {
+                at_rule.params = at_rule.params.as_deref().map(&self.0);
+                walk_at_rule_mut(self, at_rule);
+            }
+
+            fn visit_declaration_mut(&mut self, declaration: &mut CSSDeclaration) {
+                declaration.property = (self.0)(&declaration.property);
+                declaration.value = (self.0)(&declaration.value);
+            }
+        }
+
+        let mut node = self.clone();
+        TextMapper(f).visit_node_mut(&mut node);
+        node
+    }
+}
+
+/// Selector of a rule nested within a rule with the `parent` selector, see
+/// `SelectorList::resolve_nesting`.
+pub fn resolve_nested_selector(parent: &str, selector: &str) -> String {
+    match (SelectorList::parse(parent), SelectorList::parse(selector)) {
+        (Ok(parent), Ok(selectors)) => selectors.resolve_nesting(&parent).to_string(),
+        _ if selector.contains('&') => selector.replace('&', parent),
+        _ => format!("{} {}", parent, selector),
+    }
+}
+
+/// Nodes without nested rules, for browsers lacking css nesting support:
+/// nested selectors are resolved against their parent one and at-rules are
+/// hoisted out of rules, their declarations wrapped in a rule of their own.
+/// Declarations keep their order, splitting rules around nested ones.
+pub fn flatten_nodes(nodes: &[CSSNode], parent: Option<&CSSRule>) -> Vec<CSSNode> {
+    let mut flat = vec![];
+    let mut declarations: Option<CSSRule> = None;
+
+    for node in nodes {
+        match node {
+            CSSNode::CSSDeclaration(declaration) => match parent {
+                Some(parent) => declarations
+                    .get_or_insert_with(|| parent.clone())
+                    .insert_declaration(declaration),
+                None => flat.push(node.clone()),
+            },
+            CSSNode::CSSRule(rule) => {
+                flat.extend(declarations.take().map(CSSNode::CSSRule));
+                let selector = match parent {
+                    Some(parent) => resolve_nested_selector(&parent.selector, &rule.selector),
+                    None => rule.selector.to_owned(),
+                };
+                let resolved = CSSRule {
+                    selector,
+                    children: vec![],
+                    span: rule.span.to_owned(),
+                };
+                flat.extend(flatten_nodes(&rule.children, Some(&resolved)));
+            }
+            CSSNode::CSSAtRule(at_rule) => {
+                flat.extend(declarations.take().map(CSSNode::CSSRule));
+                flat.push(CSSNode::CSSAtRule(CSSAtRule {
+                    name: at_rule.name.to_owned(),
+                    params: at_rule.params.to_owned(),
+                    children: flatten_nodes(&at_rule.children, parent),
+                    span: at_rule.span.to_owned(),
+                }));
+            }
+        }
+    }
+    flat.extend(declarations.map(CSSNode::CSSRule));
+
+    flat
+}
+
 #[derive(Clone, Eq, PartialEq, Debug, Default, Display)]
 #[display(fmt = "#CSSRule({} {{ ... }})", selector)]
 pub struct CSSRule {
     pub selector: String,
     children: Vec<CSSNode>,
+    pub span: Option<Span>,
 }
 
 impl CSSRule {
@@ -38,19 +151,19 @@ impl CSSRule {
         }
     }
 
+    pub fn with_span(mut self, span: Span) -> Self {
+        self.span = Some(span);
+        self
+    }
+
+    pub fn get_selector_list(&self) -> Result<SelectorList, String> {
+        SelectorList::parse(&self.selector)
+    }
+
     pub fn get_css(&self) -> String {
-        let mut css = format!("{}{{", self.selector);
-        for child in &self.children {
-            match child {
-                CSSNode::CSSDeclaration(declaration) => {
-                    css.push_str(&format!("{}:{};", declaration.property, declaration.value))
-                }
-                CSSNode::CSSAtRule(at_rule) => css.push_str(&at_rule.get_css()),
-                CSSNode::CSSRule(rule) => css.push_str(&rule.get_css()),
-            }
-        }
-        css.push('}');
-        css
+        let mut output = Output::default();
+        CompactSerializer.write_rule(self, 0, &mut output);
+        output.css
     }
 
     pub fn with_declaration(mut self, declaration: CSSDeclaration) -> Self {
@@ -73,6 +186,14 @@ impl CSSRule {
             .push(CSSNode::CSSDeclaration(declaration.clone()));
     }
 
+    pub fn get_children(&self) -> &Vec<CSSNode> {
+        &self.children
+    }
+
+    pub fn get_children_mut(&mut self) -> &mut Vec<CSSNode> {
+        &mut self.children
+    }
+
     pub fn insert_at_rule(&mut self, at_rule: &CSSAtRule) {
         self.children.push(CSSNode::CSSAtRule(at_rule.clone()));
     }
@@ -88,6 +209,7 @@ pub struct CSSAtRule {
     pub name: String,
     pub params: Option<String>,
     children: Vec<CSSNode>,
+    pub span: Option<Span>,
 }
 
 impl CSSAtRule {
@@ -102,6 +224,11 @@ impl CSSAtRule {
         at_rule
     }
 
+    pub fn with_span(mut self, span: Span) -> Self {
+        self.span = Some(span);
+        self
+    }
+
     pub fn with_rule(mut self, rule: CSSRule) -> Self {
         self.insert_rule(&rule);
         self
@@ -130,28 +257,55 @@ impl CSSAtRule {
             .push(CSSNode::CSSDeclaration(declaration.clone()));
     }
 
+    pub fn get_children(&self) -> &Vec<CSSNode> {
+        &self.children
+    }
+
+    pub fn get_children_mut(&mut self) -> &mut Vec<CSSNode> {
+        &mut self.children
+    }
+
     pub fn get_css(&self) -> String {
-        let mut css = format!("@{}", self.name);
-        if let Some(params) = &self.params {
-            css.push_str(&format!(" {}", params));
-        }
+        let mut output = Output::default();
+        CompactSerializer.write_at_rule(self, 0, &mut output);
+        output.css
+    }
+}
 
-        if !self.children.is_empty() {
-            css.push('{');
-            for child in &self.children {
-                match child {
-                    CSSNode::CSSRule(rule) => css.push_str(&rule.get_css()),
-                    CSSNode::CSSAtRule(at_rule) => css.push_str(&at_rule.get_css()),
-                    CSSNode::CSSDeclaration(decl) => {
-                        css.push_str(&format!("{}:{};", decl.property, decl.value))
-                    }
-                }
-            }
-            css.push('}');
-        } else {
-            css.push(';');
-        }
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_flattens_nested_rules() {
+        let rule = CSSRule::new("${root}")
+            .with_declaration(CSSDeclaration::new("color", "red"))
+            .with_rule(
+                CSSRule::new("&:hover")
+                    .with_declaration(CSSDeclaration::new("color", "blue"))
+                    .with_rule(
+                        CSSRule::new("span")
+                            .with_declaration(CSSDeclaration::new("color", "green")),
+                    ),
+            )
+            .with_at_rule(
+                CSSAtRule::new("media", Some("print"))
+                    .with_declaration(CSSDeclaration::new("color", "black"))
+                    .with_rule(
+                        CSSRule::new("& > a")
+                            .with_declaration(CSSDeclaration::new("color", "gray")),
+                    ),
+            )
+            .with_declaration(CSSDeclaration::new("padding", "0"));
 
-        css
+        let css: String = flatten_nodes(&[CSSNode::CSSRule(rule)], None)
+            .iter()
+            .map(|node| node.get_css())
+            .collect();
+        assert_eq!(
+            css,
+            "${root}{color:red;}${root}:hover{color:blue;}${root}:hover span{color:green;}\
+             @media print{${root}{color:black;}${root} > a{color:gray;}}${root}{padding:0;}"
+        );
     }
 }
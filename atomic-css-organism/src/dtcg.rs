@@ -0,0 +1,426 @@
+//! Import and export of design tokens in the Design Tokens Community Group
+//! (DTCG) JSON format.
+//!
+//! Tokens can be turned into electrons through the `atomic-css` extension:
+//!
+//! ```json
+//! {
+//!   "color": {
+//!     "$type": "color",
+//!     "red": {
+//!       "$value": "#ff0000",
+//!       "$extensions": {
+//!         "atomic-css": { "electrons": [{ "name": "red", "property": "color" }] }
+//!       }
+//!     }
+//!   }
+//! }
+//! ```
+
+use serde_json::{json, Map, Value};
+
+use crate::css::*;
+use crate::electron::*;
+use crate::organism::*;
+use crate::token::*;
+
+const EXTENSION: &str = "atomic-css";
+
+struct ElectronBinding {
+    electron: ElectronName,
+    property: String,
+    token: TokenName,
+}
+
+fn get_token_value(name: &str, value: &Value) -> Result<String, String> {
+    match value {
+        Value::String(value) => Ok(value.to_owned()),
+        Value::Number(number) => Ok(number.to_string()),
+        Value::Object(dimension) => match (dimension.get("value"), dimension.get("unit")) {
+            (Some(Value::Number(value)), Some(Value::String(unit))) => {
+                Ok(format!("{}{}", value, unit))
+            }
+            _ => Err(format!("Unsupported value for token `{}`", name)),
+        },
+        Value::Array(values) if values.len() == 4 && values.iter().all(Value::is_number) => {
+            let values: Vec<String> = values.iter().map(|value| value.to_string()).collect();
+            Ok(format!("cubic-bezier({})", values.join(", ")))
+        }
+        _ => Err(format!("Unsupported value for token `{}`", name)),
+    }
+}
+
+fn get_number(value: &str) -> Option<Value> {
+    serde_json::from_str::<Value>(value)
+        .ok()
+        .filter(Value::is_number)
+}
+
+/// Converts a token value back to its DTCG form according to the token type,
+/// falling back to a string, e.g. for aliases.
+fn get_raw_value(token: &Token) -> Value {
+    let value = token.value.trim();
+    let raw_value = match token.token_type.as_deref() {
+        Some("number") => get_number(value),
+        Some("dimension") => {
+            let unit_start = value
+                .find(|c: char| !(c.is_ascii_digit() || matches!(c, '.' | '-' | '+')))
+                .unwrap_or(value.len());
+            let (number, unit) = value.split_at(unit_start);
+            get_number(number).map(|number| json!({ "value": number, "unit": unit }))
+        }
+        Some("cubicBezier") => value
+            .strip_prefix("cubic-bezier(")
+            .and_then(|value| value.strip_suffix(')'))
+            .and_then(|values| {
+                values
+                    .split(',')
+                    .map(|value| get_number(value.trim()))
+                    .collect::<Option<Vec<Value>>>()
+            })
+            .filter(|values| values.len() == 4)
+            .map(Value::Array),
+        _ => None,
+    };
+    raw_value.unwrap_or_else(|| json!(token.value))
+}
+
+fn get_bindings(name: &str, token: &Map<String, Value>) -> Result<Vec<ElectronBinding>, String> {
+    let Some(extension) = token
+        .get("$extensions")
+        .and_then(|extensions| extensions.get(EXTENSION))
+    else {
+        return Ok(vec![]);
+    };
+
+    let Some(Value::Array(electrons)) = extension.get("electrons") else {
+        return Err(format!(
+            "Invalid {} extension for token `{}`",
+            EXTENSION, name
+        ));
+    };
+
+    electrons
+        .iter()
+        .map(
+            |electron| match (electron.get("name"), electron.get("property")) {
+                (Some(Value::String(electron)), Some(Value::String(property))) => {
+                    Ok(ElectronBinding {
+                        electron: electron.to_owned(),
+                        property: property.to_owned(),
+                        token: name.to_owned(),
+                    })
+                }
+                _ => Err(format!("Invalid electron for token `{}`", name)),
+            },
+        )
+        .collect()
+}
+
+fn walk_group(
+    group: &Map<String, Value>,
+    path: &[&str],
+    inherited_type: Option<&str>,
+    tokens: &mut Vec<Token>,
+    bindings: &mut Vec<ElectronBinding>,
+) -> Result<(), String> {
+    let group_type = match group.get("$type") {
+        Some(Value::String(group_type)) => Some(group_type.as_str()),
+        _ => inherited_type,
+    };
+
+    for (key, value) in group {
+        if key.starts_with('$') {
+            continue;
+        }
+        if key.contains(['.', '{', '}']) {
+            return Err(format!("Invalid token name `{}`", key));
+        }
+
+        let mut child_path = path.to_vec();
+        child_path.push(key);
+        let name = child_path.join(".");
+
+        let Value::Object(child) = value else {
+            return Err(format!("Expected token or group at `{}`", name));
+        };
+
+        let Some(raw_value) = child.get("$value") else {
+            walk_group(child, &child_path, group_type, tokens, bindings)?;
+            continue;
+        };
+
+        let mut token = Token::new(&name, &get_token_value(&name, raw_value)?);
+        match child.get("$type") {
+            Some(Value::String(token_type)) => token = token.with_type(token_type),
+            _ => token.token_type = group_type.map(str::to_string),
+        }
+        if let Some(Value::String(description)) = child.get("$description") {
+            token = token.with_description(description);
+        }
+
+        bindings.extend(get_bindings(&name, child)?);
+        tokens.push(token);
+    }
+
+    Ok(())
+}
+
+fn insert_path(root: &mut Map<String, Value>, token: &Token, leaf: Value) -> Result<(), String> {
+    let segments: Vec<&str> = token.name.split('.').collect();
+    let (last, groups) = segments
+        .split_last()
+        .ok_or_else(|| format!("Invalid token name `{}`", token.name))?;
+
+    let mut group = root;
+    for segment in groups {
+        let entry = group
+            .entry(segment.to_string())
+            .or_insert_with(|| Value::Object(Map::new()));
+        group = match entry {
+            Value::Object(child) if !child.contains_key("$value") => child,
+            _ => return Err(format!("Token `{}` is nested in a token", token.name)),
+        };
+    }
+
+    if group.contains_key(*last) {
+        return Err(format!("Token `{}` is also a group", token.name));
+    }
+    group.insert(last.to_string(), leaf);
+    Ok(())
+}
+
+impl Organism {
+    /// Imports the tokens of a DTCG file, creating the electrons bound to
+    /// them through the `atomic-css` extension.
+    pub fn import_dtcg(&mut self, source: &str) -> Result<(), String> {
+        let root: Value =
+            serde_json::from_str(source).map_err(|error| format!("Invalid JSON: {}", error))?;
+        let Value::Object(root) = root else {
+            return Err("Expected a token group at the root".to_owned());
+        };
+
+        let mut tokens = vec![];
+        let mut bindings = vec![];
+        walk_group(&root, &[], None, &mut tokens, &mut bindings)?;
+
+        for token in &tokens {
+            self.insert_token(token);
+        }
+
+        let mut electrons: Vec<Electron> = vec![];
+        for binding in &bindings {
+            let declaration =
+                CSSDeclaration::new(&binding.property, &self.get_token_value(&binding.token)?);
+            match electrons.iter_mut().find(|e| e.name == binding.electron) {
+                Some(electron) => electron.insert_declaration(&declaration),
+                None => {
+                    electrons.push(Electron::empty(&binding.electron).with_declaration(declaration))
+                }
+            }
+            self.bind_electron(&binding.electron, &binding.property, &binding.token);
+        }
+        for electron in &electrons {
+            self.insert_electron(electron);
+        }
+
+        Ok(())
+    }
+
+    /// Exports the tokens of the organism, along with the electrons bound to
+    /// them, as a DTCG file.
+    pub fn export_dtcg(&self) -> Result<String, String> {
+        let mut tokens: Vec<&Token> = self.tokens.values().collect();
+        tokens.sort_by(|a, b| a.name.cmp(&b.name));
+
+        let mut root = Map::new();
+        for token in tokens {
+            let mut leaf = Map::new();
+            leaf.insert("$value".to_owned(), get_raw_value(token));
+            if let Some(token_type) = &token.token_type {
+                leaf.insert("$type".to_owned(), json!(token_type));
+            }
+            if let Some(description) = &token.description {
+                leaf.insert("$description".to_owned(), json!(description));
+            }
+
+            let mut bindings: Vec<&(ElectronName, String)> = self
+                .token_bindings
+                .iter()
+                .filter(|(_, bound_token)| **bound_token == token.name)
+                .map(|(binding, _)| binding)
+                .collect();
+            bindings.sort();
+
+            let electrons: Vec<Value> = bindings
+                .into_iter()
+                .filter(|(name, property)| {
+                    self.electrons
+                        .get(name)
+                        .is_some_and(|electron| electron.get_declaration(property).is_some())
+                })
+                .map(|(name, property)| json!({ "name": name, "property": property }))
+                .collect();
+            if !electrons.is_empty() {
+                leaf.insert(
+                    "$extensions".to_owned(),
+                    json!({ EXTENSION: { "electrons": electrons } }),
+                );
+            }
+
+            insert_path(&mut root, token, Value::Object(leaf))?;
+        }
+
+        serde_json::to_string_pretty(&Value::Object(root))
+            .map_err(|error| format!("Failed to serialize tokens: {}", error))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::overlay::*;
+
+    const TOKENS: &str = r##"
+{
+  "color": {
+    "$type": "color",
+    "base": {
+      "red": { "$value": "#ff0000", "$description": "Brand red" },
+      "green": { "$value": "#00ff00" }
+    },
+    "text": {
+      "$value": "{color.base.red}",
+      "$extensions": {
+        "atomic-css": { "electrons": [{ "name": "red", "property": "color" }] }
+      }
+    },
+    "background": {
+      "$value": "{color.base.green}",
+      "$extensions": {
+        "atomic-css": {
+          "electrons": [{ "name": "bg_green", "property": "background-color" }]
+        }
+      }
+    }
+  },
+  "space": {
+    "md": { "$type": "dimension", "$value": { "value": 1, "unit": "rem" } }
+  },
+  "easing": {
+    "standard": { "$type": "cubicBezier", "$value": [0.4, 0, 0.2, 1] }
+  }
+}
+"##;
+
+    #[test]
+    fn it_imports_tokens() {
+        let mut organism = Organism::new();
+        organism
+            .import_dtcg(TOKENS)
+            .expect("Failed to import tokens");
+
+        assert_eq!(organism.tokens.len(), 6);
+        assert_eq!(
+            organism.tokens["color.base.red"],
+            Token::new("color.base.red", "#ff0000")
+                .with_type("color")
+                .with_description("Brand red")
+        );
+        assert_eq!(organism.tokens["space.md"].value, "1rem");
+        assert_eq!(
+            organism.tokens["easing.standard"].value,
+            "cubic-bezier(0.4, 0, 0.2, 1)"
+        );
+        assert_eq!(
+            organism.electrons["red"],
+            Electron::new("red", "color", "#ff0000")
+        );
+        assert_eq!(
+            organism.token_bindings[&("bg_green".to_owned(), "background-color".to_owned())],
+            "color.background"
+        );
+    }
+
+    #[test]
+    fn it_round_trips_tokens() {
+        let mut organism = Organism::new();
+        organism
+            .import_dtcg(TOKENS)
+            .expect("Failed to import tokens");
+        let exported = organism.export_dtcg().expect("Failed to export tokens");
+
+        let mut reimported = Organism::new();
+        reimported
+            .import_dtcg(&exported)
+            .expect("Failed to import exported tokens");
+
+        assert_eq!(reimported.tokens, organism.tokens);
+        assert_eq!(reimported.electrons, organism.electrons);
+        assert_eq!(reimported.token_bindings, organism.token_bindings);
+        assert_eq!(reimported.export_dtcg(), Ok(exported.to_owned()));
+
+        let exported: Value = serde_json::from_str(&exported).unwrap();
+        assert_eq!(
+            exported["easing"]["standard"]["$value"],
+            json!([0.4, 0, 0.2, 1])
+        );
+        assert_eq!(
+            exported["space"]["md"]["$value"],
+            json!({ "value": 1, "unit": "rem" })
+        );
+    }
+
+    #[test]
+    fn it_binds_electron_declarations_to_their_own_token() {
+        let mut organism = Organism::new();
+        organism
+            .import_dtcg(
+                r##"
+{
+  "text": {
+    "$value": "#ff0000",
+    "$extensions": {
+      "atomic-css": { "electrons": [{ "name": "accent", "property": "color" }] }
+    }
+  },
+  "border": {
+    "$value": "#00ff00",
+    "$extensions": {
+      "atomic-css": { "electrons": [{ "name": "accent", "property": "border-color" }] }
+    }
+  }
+}
+"##,
+            )
+            .expect("Failed to import tokens");
+
+        let overlay = Overlay::new("acme").with_token(Token::new("border", "#0000ff"));
+        let acme = organism.apply_overlay(&overlay).expect("Failed to apply");
+        assert_eq!(
+            acme.electrons["accent"].declarations,
+            vec![
+                CSSDeclaration::new("border-color", "#0000ff"),
+                CSSDeclaration::new("color", "#ff0000"),
+            ]
+        );
+
+        let exported = organism.export_dtcg().expect("Failed to export tokens");
+        let mut reimported = Organism::new();
+        reimported
+            .import_dtcg(&exported)
+            .expect("Failed to import exported tokens");
+        assert_eq!(reimported.electrons, organism.electrons);
+        assert_eq!(reimported.token_bindings, organism.token_bindings);
+    }
+
+    #[test]
+    fn it_rejects_invalid_tokens() {
+        let mut organism = Organism::new();
+        assert!(organism.import_dtcg("[]").is_err());
+        assert!(organism.import_dtcg(r##"{ "color": "#ff0000" }"##).is_err());
+        assert!(organism
+            .import_dtcg(r#"{ "a": { "$value": "{b}" }, "b": { "$value": "{a}" }, "c": { "$value": "{a}", "$extensions": { "atomic-css": { "electrons": [{ "name": "c", "property": "color" }] } } } }"#)
+            .is_err());
+    }
+}
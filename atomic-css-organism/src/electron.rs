@@ -1,24 +1,72 @@
 pub type ElectronName = String;
 use derive_more::Display;
 
+use crate::css::*;
+use crate::serializer::*;
+use crate::variant::*;
+
 #[derive(Clone, Eq, PartialEq, Debug, Default, Display)]
 #[display(fmt = "#Electron({})", name)]
 pub struct Electron {
     pub name: ElectronName,
-    pub property: String,
-    pub value: String,
+    pub declarations: Vec<CSSDeclaration>,
+    pub span: Option<Span>,
 }
 
 impl Electron {
     pub fn new(name: &str, property: &str, value: &str) -> Self {
+        Self::empty(name).with_declaration(CSSDeclaration::new(property, value))
+    }
+
+    pub fn empty(name: &str) -> Self {
         Self {
             name: name.to_string(),
-            property: property.to_string(),
-            value: value.to_string(),
+            ..Default::default()
         }
     }
 
+    pub fn with_span(mut self, span: Span) -> Self {
+        self.span = Some(span);
+        self
+    }
+
+    pub fn with_declaration(mut self, declaration: CSSDeclaration) -> Self {
+        self.insert_declaration(&declaration);
+        self
+    }
+
+    pub fn insert_declaration(&mut self, declaration: &CSSDeclaration) {
+        self.declarations.push(declaration.clone());
+    }
+
+    pub fn get_declaration(&self, property: &str) -> Option<&CSSDeclaration> {
+        self.declarations
+            .iter()
+            .find(|declaration| declaration.property == property)
+    }
+
     pub fn get_css(&self) -> String {
-        format!(".{}{{{}:{}}}", self.name, self.property, self.value)
+        let mut output = Output::default();
+        CompactSerializer.write_electron_rule(&self.get_rule(), &mut output);
+        output.css
+    }
+
+    /// Same as `get_css` as a css rule, e.g. for serializers.
+    pub fn get_rule(&self) -> CSSRule {
+        let mut rule = CSSRule::new(&format!(".{}", self.name));
+        rule.span = self.span.to_owned();
+        for declaration in &self.declarations {
+            rule.insert_declaration(declaration);
+        }
+        rule
+    }
+
+    pub fn get_variant_rule(&self, reference: &ElectronReference) -> CSSRule {
+        let mut rule = CSSRule::new(&reference.get_selector());
+        rule.span = self.span.to_owned();
+        for declaration in &self.declarations {
+            rule.insert_declaration(declaration);
+        }
+        rule
     }
 }
@@ -0,0 +1,558 @@
+//! Import of plain css, e.g. when migrating an existing stylesheet.
+//!
+//! Rules made of a single class with a single declaration become electrons
+//! and the custom properties of `:root` become tokens, the other rules are
+//! kept in a molecule as they are:
+//!
+//! ```css
+//! :root { --brand: #ff0000 }
+//! .red { color: var(--brand) }
+//! .card > a { padding: 1rem }
+//! ```
+//!
+//! ```lisp
+//! (token `brand` `#ff0000`)
+//! (electron `red` (color `var(--brand)`))
+//!
+//! (molecule `legacy`
+//!   (& `.card > a` (padding `1rem`)))
+//! ```
+
+use crate::css::*;
+use crate::electron::*;
+use crate::selector::*;
+use crate::serializer::*;
+use crate::token::*;
+use crate::visitor::*;
+
+/// Maximum nesting of blocks, so that arbitrary input cannot overflow the
+/// stack of the parser.
+const MAX_DEPTH: usize = 32;
+
+struct CSSParser<'a> {
+    source: &'a str,
+    position: usize,
+}
+
+impl<'a> CSSParser<'a> {
+    fn new(source: &'a str) -> Self {
+        Self {
+            source,
+            position: 0,
+        }
+    }
+
+    fn error(&self, message: &str) -> String {
+        format!("{} at offset {}", message, self.position)
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.source[self.position..].chars().next()
+    }
+
+    fn skip_comment(&mut self) -> Result<bool, String> {
+        if !self.source[self.position..].starts_with("/*") {
+            return Ok(false);
+        }
+        match self.source[self.position + 2..].find("*/") {
+            Some(end) => {
+                self.position += end + 4;
+                Ok(true)
+            }
+            None => Err(self.error("Unterminated comment")),
+        }
+    }
+
+    fn skip_whitespace(&mut self) -> Result<(), String> {
+        loop {
+            match self.peek() {
+                Some(c) if c.is_whitespace() => self.position += c.len_utf8(),
+                Some('/') if self.skip_comment()? => {}
+                _ => return Ok(()),
+            }
+        }
+    }
+
+    /// Text up to the next `{`, `;` or `}` out of strings and brackets,
+    /// comments removed.
+    fn parse_prelude(&mut self) -> Result<String, String> {
+        let mut prelude = String::new();
+        let mut brackets = 0;
+        while let Some(c) = self.peek() {
+            match c {
+                '{' | ';' | '}' if brackets == 0 => break,
+                '/' if self.skip_comment()? => continue,
+                '"' | '\'' => {
+                    let start = self.position;
+                    self.position += 1;
+                    loop {
+                        match self.peek() {
+                            Some('\\') => self.position += 1,
+                            Some(q) if q == c => break,
+                            Some('\n') | None => return Err(self.error("Unterminated string")),
+                            Some(_) => {}
+                        }
+                        self.position += self.peek().map_or(0, char::len_utf8);
+                    }
+                    self.position += 1;
+                    prelude.push_str(&self.source[start..self.position]);
+                    continue;
+                }
+                '\\' => {
+                    prelude.push(c);
+                    self.position += 1;
+                    if let Some(escaped) = self.peek() {
+                        prelude.push(escaped);
+                        self.position += escaped.len_utf8();
+                    }
+                    continue;
+                }
+                '(' | '[' => brackets += 1,
+                ')' | ']' => brackets = usize::saturating_sub(brackets, 1),
+                _ => {}
+            }
+            prelude.push(c);
+            self.position += c.len_utf8();
+        }
+        Ok(collapse_whitespace(&prelude))
+    }
+
+    fn parse_block(&mut self, depth: usize) -> Result<Vec<CSSNode>, String> {
+        if depth > MAX_DEPTH {
+            return Err(self.error("Maximum nesting depth exceeded"));
+        }
+
+        let mut nodes = vec![];
+        loop {
+            self.skip_whitespace()?;
+            let start = self.position;
+            match self.peek() {
+                None if depth > 0 => return Err(self.error("Unclosed block")),
+                None => return Ok(nodes),
+                Some('}') if depth > 0 => {
+                    self.position += 1;
+                    return Ok(nodes);
+                }
+                Some('}') => return Err(self.error("Unexpected `}`")),
+                _ => {}
+            }
+
+            let prelude = self.parse_prelude()?;
+            let has_block = self.peek() == Some('{');
+            if matches!(self.peek(), Some('{' | ';')) {
+                self.position += 1;
+            }
+            let children = match has_block {
+                true => self.parse_block(depth + 1)?,
+                false => vec![],
+            };
+            let span = start..self.position;
+
+            if let Some(at_rule) = prelude.strip_prefix('@') {
+                let (name, params) = match at_rule.find(|c: char| c.is_whitespace() || c == '(') {
+                    Some(end) => (&at_rule[..end], at_rule[end..].trim()),
+                    None => (at_rule, ""),
+                };
+                let params = Some(params).filter(|params| !params.is_empty());
+                let mut at_rule = CSSAtRule::new(name, params).with_span(span);
+                *at_rule.get_children_mut() = children;
+                nodes.push(CSSNode::CSSAtRule(at_rule));
+            } else if has_block {
+                if prelude.is_empty() {
+                    return Err(self.error("Expected selector"));
+                }
+                let mut rule = CSSRule::new(&prelude).with_span(span);
+                *rule.get_children_mut() = children;
+                nodes.push(CSSNode::CSSRule(rule));
+            } else if !prelude.is_empty() {
+                let Some((property, value)) = prelude.split_once(':') else {
+                    return Err(self.error("Expected declaration"));
+                };
+                if depth == 0 {
+                    return Err(self.error("Unexpected declaration"));
+                }
+                nodes.push(CSSNode::CSSDeclaration(
+                    CSSDeclaration::new(property.trim(), value.trim()).with_span(span),
+                ));
+            }
+        }
+    }
+}
+
+/// Parses a stylesheet, nested rules included.
+pub fn parse_css(source: &str) -> Result<Vec<CSSNode>, String> {
+    CSSParser::new(source)
+        .parse_block(0)
+        .map_err(|error| format!("Invalid css: {}", error))
+}
+
+/// Tokens, electrons and rules found in a stylesheet, see `import_css`.
+#[derive(Clone, Eq, PartialEq, Debug, Default)]
+pub struct CSSImport {
+    pub tokens: Vec<Token>,
+    pub electrons: Vec<Electron>,
+    pub nodes: Vec<CSSNode>,
+}
+
+/// Name of the electron of a rule made of a single class with a single
+/// declaration, e.g. `.red { color: red }`.
+fn get_electron_name(rule: &CSSRule) -> Option<String> {
+    let [CSSNode::CSSDeclaration(_)] = &rule.get_children()[..] else {
+        return None;
+    };
+    let selectors = rule.get_selector_list().ok()?;
+    match &selectors.0[..] {
+        [ComplexSelector { first, rest }] if rest.is_empty() => match &first.0[..] {
+            [SimpleSelector::Class(name)] => Some(name.to_owned()),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+/// Finds a rule setting `property` with the specificity of a single class,
+/// in a flattened tree.
+struct PropertyFinder<'a> {
+    property: &'a str,
+    found: bool,
+}
+
+impl Visitor for PropertyFinder<'_> {
+    fn visit_rule(&mut self, rule: &CSSRule) {
+        let sets_property = rule.get_children().iter().any(|child| {
+            matches!(child, CSSNode::CSSDeclaration(declaration) if declaration.property == self.property)
+        });
+        // unparsable selectors may have any specificity
+        let has_class_specificity = rule.get_selector_list().map_or(true, |selectors| {
+            selectors.0.iter().any(|selector| {
+                selector.get_specificity(Specificity::default()) == Specificity::new(0, 1, 0)
+            })
+        });
+        self.found |= sets_property && has_class_specificity;
+        walk_rule(self, rule);
+    }
+}
+
+impl CSSImport {
+    /// Turns the top level single class, single declaration rules into
+    /// electrons, the first one winning when a class has several of them,
+    /// and the custom properties of top level `:root` rules into tokens.
+    /// Tokens and electrons are output before any rule, so they are kept as
+    /// rules when an earlier rule with the same specificity sets the same
+    /// property, which they would no longer override.
+    pub fn new(nodes: Vec<CSSNode>) -> Self {
+        let mut import = Self::default();
+        for node in nodes {
+            if let CSSNode::CSSRule(rule) = &node {
+                if rule.selector == ":root" {
+                    let mut rule = rule.clone();
+                    rule.get_children_mut()
+                        .retain(|child| match import.get_token(child) {
+                            Some(token) => {
+                                import.tokens.push(token);
+                                false
+                            }
+                            None => true,
+                        });
+                    if !rule.get_children().is_empty() {
+                        import.nodes.push(CSSNode::CSSRule(rule));
+                    }
+                    continue;
+                }
+
+                let electron = get_electron_name(rule).filter(|name| {
+                    !import.electrons.iter().any(|e| e.name == *name)
+                        && check_declaration(&rule.get_children()[0]).is_ok()
+                        && !import.is_overridden(&rule.get_children()[0])
+                });
+                if let (Some(name), [CSSNode::CSSDeclaration(declaration)]) =
+                    (electron, &rule.get_children()[..])
+                {
+                    let mut electron = Electron::empty(&name).with_declaration(declaration.clone());
+                    electron.span = rule.span.to_owned();
+                    import.electrons.push(electron);
+                    continue;
+                }
+            }
+            import.nodes.push(node);
+        }
+        import
+    }
+
+    /// Whether a rule kept so far sets the property of `declaration` with
+    /// the specificity of a single class, i.e. the one of electrons and of
+    /// `:root`.
+    fn is_overridden(&self, declaration: &CSSNode) -> bool {
+        let CSSNode::CSSDeclaration(declaration) = declaration else {
+            return false;
+        };
+        let mut finder = PropertyFinder {
+            property: &declaration.property,
+            found: false,
+        };
+        finder.visit_nodes(&flatten_nodes(&self.nodes, None));
+        finder.found
+    }
+
+    /// Token of a custom property declaration, e.g. `brand` for `--brand`,
+    /// unless its value cannot be written in the DSL or is overridden.
+    fn get_token(&self, node: &CSSNode) -> Option<Token> {
+        let CSSNode::CSSDeclaration(declaration) = node else {
+            return None;
+        };
+        let name = declaration.property.strip_prefix("--")?;
+        // braces would be read as token aliases
+        if check_text(&declaration.value).is_err()
+            || declaration.value.contains(['{', '}'])
+            || self.is_overridden(node)
+        {
+            return None;
+        }
+        Some(Token::new(name, &declaration.value))
+    }
+
+    /// DSL source declaring the tokens and electrons, then a `molecule_name`
+    /// molecule holding the remaining rules. Rules that cannot be written in
+    /// the DSL are left as comments.
+    pub fn get_dsl(&self, molecule_name: &str) -> String {
+        let mut dsl = String::new();
+
+        for token in &self.tokens {
+            dsl.push_str(&format!("(token `{}` `{}`)\n", token.name, token.value));
+        }
+        for electron in &self.electrons {
+            let declarations: Vec<String> = electron
+                .declarations
+                .iter()
+                .map(|declaration| format!("({} `{}`)", declaration.property, declaration.value))
+                .collect();
+            dsl.push_str(&format!(
+                "(electron `{}` {})\n",
+                electron.name,
+                declarations.join(" ")
+            ));
+        }
+
+        let mut rules = vec![];
+        for node in &self.nodes {
+            match write_node(node, 1) {
+                Ok(rule) => rules.push(rule),
+                Err(reason) => {
+                    if !dsl.is_empty() {
+                        dsl.push('\n');
+                    }
+                    dsl.push_str(&format!("; skipped, {}:\n; {}\n", reason, node.get_css()));
+                }
+            }
+        }
+
+        if !rules.is_empty() {
+            if !dsl.is_empty() {
+                dsl.push('\n');
+            }
+            dsl.push_str(&format!("(molecule `{}`", molecule_name));
+            for rule in rules {
+                dsl.push_str(&format!("\n  {}", rule));
+            }
+            dsl.push_str(")\n");
+        }
+
+        dsl
+    }
+}
+
+/// Parses a stylesheet and extracts its electrons, see `CSSImport::new`.
+pub fn import_css(source: &str) -> Result<CSSImport, String> {
+    Ok(CSSImport::new(parse_css(source)?))
+}
+
+/// DSL strings are delimited by backticks, and `${` starts a placeholder.
+fn check_text(text: &str) -> Result<(), String> {
+    if text.contains('`') {
+        return Err(format!("`{}` contains a backtick", text.replace('`', "'")));
+    }
+    if text.contains("${") {
+        return Err(format!("`{}` contains a placeholder", text));
+    }
+    Ok(())
+}
+
+/// Declarations are functions named after their property, so that the
+/// property must be a DSL identifier.
+fn check_declaration(node: &CSSNode) -> Result<(), String> {
+    let CSSNode::CSSDeclaration(declaration) = node else {
+        return Ok(());
+    };
+    let mut chars = declaration.property.chars();
+    let is_identifier = chars.next().is_some_and(|c| c.is_ascii_alphabetic())
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+    if !is_identifier {
+        return Err(format!(
+            "property `{}` is not an identifier",
+            declaration.property
+        ));
+    }
+    check_text(&declaration.value)
+}
+
+/// A form with its children, on one line when there is a single short child.
+fn write_form(head: String, children: Vec<String>, indent: usize) -> String {
+    match &children[..] {
+        [] => format!("{})", head),
+        [child] if !child.contains('\n') && head.len() + child.len() < 80 => {
+            format!("{} {})", head, child)
+        }
+        _ => {
+            let indent = "  ".repeat(indent + 1);
+            let children: Vec<String> = children
+                .iter()
+                .map(|child| format!("\n{}{}", indent, child))
+                .collect();
+            format!("{}{})", head, children.concat())
+        }
+    }
+}
+
+fn write_children(children: &[CSSNode], indent: usize) -> Result<Vec<String>, String> {
+    children
+        .iter()
+        .map(|child| write_node(child, indent + 1))
+        .collect()
+}
+
+fn write_node(node: &CSSNode, indent: usize) -> Result<String, String> {
+    match node {
+        CSSNode::CSSDeclaration(declaration) => {
+            check_declaration(node)?;
+            Ok(format!(
+                "({} `{}`)",
+                declaration.property, declaration.value
+            ))
+        }
+        CSSNode::CSSRule(rule) => {
+            check_text(&rule.selector)?;
+            rule.get_selector_list()?;
+            let children = write_children(rule.get_children(), indent)?;
+            Ok(write_form(
+                format!("(& `{}`", rule.selector),
+                children,
+                indent,
+            ))
+        }
+        CSSNode::CSSAtRule(at_rule) => {
+            check_text(&at_rule.name)?;
+            let children = write_children(at_rule.get_children(), indent)?;
+            let head = match (&at_rule.params, children.is_empty()) {
+                (Some(params), _) => {
+                    check_text(params)?;
+                    format!("(@ `{}` `{}`", at_rule.name, params)
+                }
+                (None, true) => format!("(@ `{}`", at_rule.name),
+                // children follow the parameters, even empty ones
+                (None, false) => format!("(@ `{}` ``", at_rule.name),
+            };
+            Ok(write_form(head, children, indent))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_parses_css() {
+        let nodes = parse_css(
+            r#"
+/* legacy */
+@charset "utf-8";
+.a, .b > c { color: red; background: url("a;b.png") }
+.card {
+  padding: 1rem;
+  &:hover { color: blue; }
+  @media print { display: none }
+}
+"#,
+        )
+        .unwrap();
+        let css: String = nodes.iter().map(|node| node.get_css()).collect();
+        assert_eq!(
+            css,
+            "@charset \"utf-8\";.a, .b > c{color:red;background:url(\"a;b.png\");}\
+             .card{padding:1rem;&:hover{color:blue;}@media print{display:none;}}"
+        );
+
+        assert!(parse_css(".a { color: red").is_err());
+        assert!(parse_css("color: red;").is_err());
+        assert!(parse_css("/* a").is_err());
+        assert!(parse_css(&".a{".repeat(MAX_DEPTH + 1)).is_err());
+    }
+
+    #[test]
+    fn it_keeps_the_cascade() {
+        let import = import_css(
+            r#"
+:root { --brand: #ff0000; color-scheme: light }
+[data-link] { color: blue }
+.red { color: var(--brand) }
+.bold { font-weight: bold }
+.card > a { --brand: #0000ff }
+:root { --brand: #00ff00 }
+"#,
+        )
+        .unwrap();
+
+        // `[data-link]` would override `.red` once moved above it
+        assert_eq!(
+            import.get_dsl("legacy"),
+            r#"(token `brand` `#ff0000`)
+(token `brand` `#00ff00`)
+(electron `bold` (font-weight `bold`))
+
+; skipped, property `--brand` is not an identifier:
+; .card > a{--brand:#0000ff;}
+
+(molecule `legacy`
+  (& `:root` (color-scheme `light`))
+  (& `[data-link]` (color `blue`))
+  (& `.red` (color `var(--brand)`)))
+"#
+        );
+    }
+
+    #[test]
+    fn it_extracts_electrons() {
+        let import = import_css(
+            r#"
+.red { color: #ff0000 }
+.red { color: #cc0000 }
+.truncate { overflow: hidden; white-space: nowrap }
+.card > a { padding: 1rem }
+.vendor { -webkit-appearance: none }
+@font-face { font-family: Inter }
+.quote::before { content: "`" }
+"#,
+        )
+        .unwrap();
+
+        assert_eq!(
+            import.get_dsl("legacy"),
+            r#"(electron `red` (color `#ff0000`))
+
+; skipped, property `-webkit-appearance` is not an identifier:
+; .vendor{-webkit-appearance:none;}
+
+; skipped, `"'"` contains a backtick:
+; .quote::before{content:"`";}
+
+(molecule `legacy`
+  (& `.red` (color `#cc0000`))
+  (& `.truncate`
+    (overflow `hidden`)
+    (white-space `nowrap`))
+  (& `.card > a` (padding `1rem`))
+  (@ `font-face` `` (font-family `Inter`)))
+"#
+        );
+    }
+}
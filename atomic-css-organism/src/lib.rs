@@ -1,5 +1,18 @@
 pub mod atom;
+pub mod color;
 pub mod css;
+pub mod dtcg;
 pub mod electron;
+pub mod importer;
+pub mod lint;
 pub mod molecule;
 pub mod organism;
+pub mod overlay;
+pub mod prop;
+pub mod selector;
+pub mod serializer;
+pub mod source_map;
+pub mod theme;
+pub mod token;
+pub mod variant;
+pub mod visitor;
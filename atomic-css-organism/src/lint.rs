@@ -0,0 +1,569 @@
+use derive_more::Display;
+use std::collections::HashSet;
+use std::fmt;
+use std::str::FromStr;
+
+use crate::atom::*;
+use crate::color::*;
+use crate::css::*;
+use crate::electron::*;
+use crate::molecule::*;
+use crate::organism::*;
+use crate::selector::*;
+use crate::variant::*;
+
+#[derive(Clone, Copy, Eq, PartialEq, Debug, Display)]
+pub enum ContrastLevel {
+    AA,
+    AAA,
+}
+
+impl ContrastLevel {
+    /// Minimum WCAG 2.x contrast ratio for normal sized text.
+    pub fn required_ratio(&self) -> f64 {
+        match self {
+            ContrastLevel::AA => 4.5,
+            ContrastLevel::AAA => 7.0,
+        }
+    }
+}
+
+impl FromStr for ContrastLevel {
+    type Err = String;
+
+    fn from_str(level: &str) -> Result<Self, Self::Err> {
+        match level.to_ascii_lowercase().as_str() {
+            "aa" => Ok(ContrastLevel::AA),
+            "aaa" => Ok(ContrastLevel::AAA),
+            _ => Err(format!("Unknown contrast level `{}`", level)),
+        }
+    }
+}
+
+#[derive(Clone, PartialEq, Debug, Display)]
+#[display(
+    fmt = "{}.{}: `{}` on `{}` has a contrast ratio of {}:1, {} requires {}:1",
+    molecule,
+    atom,
+    color,
+    background,
+    "format_ratio(*ratio)",
+    level,
+    "format_ratio(level.required_ratio())"
+)]
+pub struct ContrastIssue {
+    pub molecule: MoleculeName,
+    pub atom: AtomName,
+    pub color: ElectronName,
+    pub background: ElectronName,
+    pub ratio: f64,
+    pub level: ContrastLevel,
+}
+
+/// Electrons applied to the same atom setting the same property with the
+/// same condition and variants. Imported electrons come first, in import
+/// order, followed by the atom's own electrons: the last one wins, unless it
+/// only partly overrides an earlier one, both being kept and the stylesheet
+/// order deciding.
+#[derive(Clone, Eq, PartialEq, Debug)]
+pub struct ElectronConflict {
+    pub molecule: MoleculeName,
+    pub atom: AtomName,
+    pub property: String,
+    pub electrons: Vec<String>,
+    pub winner: String,
+}
+
+impl fmt::Display for ElectronConflict {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{}.{}: `{}` all set `{}`, `{}` wins",
+            self.molecule,
+            self.atom,
+            self.electrons.join("`, `"),
+            self.property,
+            self.winner
+        )
+    }
+}
+
+/// Limits for the rules of molecules, which must stay close to the
+/// specificity of the electrons for atomic classes to keep working.
+#[derive(Clone, Copy, Eq, PartialEq, Debug)]
+pub struct SpecificityBudget {
+    pub max_specificity: Specificity,
+    pub max_descendant_depth: usize,
+}
+
+impl Default for SpecificityBudget {
+    fn default() -> Self {
+        Self {
+            max_specificity: Specificity::new(0, 3, 0),
+            max_descendant_depth: 2,
+        }
+    }
+}
+
+#[derive(Clone, Eq, PartialEq, Debug, Display)]
+pub enum SpecificityIssueKind {
+    #[display(fmt = "has a specificity of {}, the budget is {}", _0, _1)]
+    OverBudget(Specificity, Specificity),
+    #[display(fmt = "uses an id")]
+    Id,
+    #[display(fmt = "uses `!important` for `{}`", _0)]
+    Important(String),
+    #[display(fmt = "has {} descendant combinators, the limit is {}", _0, _1)]
+    DescendantDepth(usize, usize),
+}
+
+/// A molecule rule breaking the specificity budget. Nested selectors are
+/// listed from the outermost one, e.g. `${root} &:hover`.
+#[derive(Clone, Eq, PartialEq, Debug, Display)]
+#[display(fmt = "{}: `{}` {}", molecule, selector, kind)]
+pub struct SpecificityIssue {
+    pub molecule: MoleculeName,
+    pub selector: String,
+    pub kind: SpecificityIssueKind,
+}
+
+fn is_important(value: &str) -> bool {
+    let value = value.to_lowercase().replace(' ', "");
+    value.ends_with("!important")
+}
+
+impl Organism {
+    fn check_node_specificity(
+        &self,
+        molecule_name: &str,
+        node: &CSSNode,
+        parent: Option<(&str, Specificity, usize)>,
+        budget: &SpecificityBudget,
+        issues: &mut Vec<SpecificityIssue>,
+    ) {
+        let (rule, children) = match node {
+            CSSNode::CSSRule(rule) => (Some(rule), rule.get_children()),
+            CSSNode::CSSAtRule(at_rule) => (None, at_rule.get_children()),
+            CSSNode::CSSDeclaration(_) => return,
+        };
+
+        // at-rules do not change the selector of the rules they contain
+        let current = match rule {
+            Some(rule) => {
+                let Ok(selectors) = rule.get_selector_list() else {
+                    return;
+                };
+                let (parent_selector, parent_specificity, parent_depth) =
+                    parent.unwrap_or(("", Specificity::default(), 0));
+                let mut specificity = Specificity::default();
+                let mut depth = 0;
+                for complex in &selectors.0 {
+                    // a nested selector without `&` is relative to its parent
+                    let (complex_specificity, complex_depth) =
+                        if complex.has_nesting() || parent.is_none() {
+                            (complex.get_specificity(parent_specificity), 0)
+                        } else {
+                            (
+                                complex.get_specificity(parent_specificity) + parent_specificity,
+                                1,
+                            )
+                        };
+                    specificity = specificity.max(complex_specificity);
+                    depth = depth.max(complex_depth + complex.get_descendant_depth());
+                }
+                let selector = match parent {
+                    Some(_) => format!("{} {}", parent_selector, rule.selector),
+                    None => rule.selector.to_owned(),
+                };
+                Some((selector, specificity, parent_depth + depth))
+            }
+            None => parent
+                .map(|(selector, specificity, depth)| (selector.to_owned(), specificity, depth)),
+        };
+
+        if let Some((selector, specificity, depth)) = &current {
+            let mut push = |kind| {
+                issues.push(SpecificityIssue {
+                    molecule: molecule_name.to_owned(),
+                    selector: selector.to_owned(),
+                    kind,
+                })
+            };
+            if rule.is_some() {
+                if specificity.ids > 0 {
+                    push(SpecificityIssueKind::Id);
+                }
+                if *specificity > budget.max_specificity {
+                    push(SpecificityIssueKind::OverBudget(
+                        *specificity,
+                        budget.max_specificity,
+                    ));
+                }
+                if *depth > budget.max_descendant_depth {
+                    push(SpecificityIssueKind::DescendantDepth(
+                        *depth,
+                        budget.max_descendant_depth,
+                    ));
+                }
+            }
+            for child in children {
+                if let CSSNode::CSSDeclaration(declaration) = child {
+                    if is_important(&declaration.value) {
+                        push(SpecificityIssueKind::Important(
+                            declaration.property.to_owned(),
+                        ));
+                    }
+                }
+            }
+        }
+
+        for child in children {
+            self.check_node_specificity(
+                molecule_name,
+                child,
+                current.as_ref().map(|(selector, specificity, depth)| {
+                    (selector.as_str(), *specificity, *depth)
+                }),
+                budget,
+                issues,
+            );
+        }
+    }
+
+    /// Reports the molecule rules, nested ones included, using ids or
+    /// `!important`, or going over the specificity or descendant budget.
+    pub fn check_specificity(&self, budget: &SpecificityBudget) -> Vec<SpecificityIssue> {
+        let mut issues = vec![];
+
+        let mut molecule_names: Vec<&MoleculeName> = self.molecules.keys().collect();
+        molecule_names.sort();
+
+        for molecule_name in molecule_names {
+            for node in self.molecules[molecule_name].get_nodes() {
+                self.check_node_specificity(molecule_name, node, None, budget, &mut issues);
+            }
+        }
+
+        issues
+    }
+
+    /// Collects the electrons applied to an atom, following its imports.
+    fn resolve_atom_electrons(
+        &self,
+        molecule_name: &str,
+        atom_name: &str,
+        visited: &mut HashSet<(String, String)>,
+        electrons: &mut Vec<ElectronName>,
+    ) {
+        if !visited.insert((molecule_name.to_string(), atom_name.to_string())) {
+            return;
+        }
+        let Some(molecule) = self.molecules.get(molecule_name) else {
+            return;
+        };
+        if let Some(atom_electrons) = molecule.get_atom_electrons(atom_name) {
+            electrons.extend(atom_electrons.iter().cloned());
+        }
+        if let Some(extends) = molecule.get_atom_extends(atom_name) {
+            for extended_atom in extends {
+                self.resolve_atom_electrons(molecule_name, extended_atom, visited, electrons);
+            }
+        }
+        if let Some(imports) = molecule.get_atom_imports(atom_name) {
+            for (imported_molecule, imported_atom) in imports {
+                self.resolve_atom_electrons(imported_molecule, imported_atom, visited, electrons);
+            }
+        }
+    }
+
+    fn get_electron_colors(
+        &self,
+        electrons: &[ElectronName],
+        property: &str,
+    ) -> Vec<(&Electron, Color)> {
+        electrons
+            .iter()
+            .filter_map(|name| self.electrons.get(name))
+            .filter_map(|electron| {
+                let declaration = electron.get_declaration(property)?;
+                Some((electron, Color::parse(&declaration.value).ok()?))
+            })
+            .collect()
+    }
+
+    /// Reports every atom combining a `color` and a `background-color` electron
+    /// whose contrast ratio is below the one required by `level`.
+    pub fn check_contrast(&self, level: ContrastLevel) -> Vec<ContrastIssue> {
+        let mut issues = vec![];
+
+        let mut molecule_names: Vec<&MoleculeName> = self.molecules.keys().collect();
+        molecule_names.sort();
+
+        for molecule_name in molecule_names {
+            let mut atom_names: Vec<&AtomName> =
+                self.molecules[molecule_name].atoms.keys().collect();
+            atom_names.sort();
+
+            for atom_name in atom_names {
+                let mut electrons = vec![];
+                self.resolve_atom_electrons(
+                    molecule_name,
+                    atom_name,
+                    &mut HashSet::new(),
+                    &mut electrons,
+                );
+
+                let colors = self.get_electron_colors(&electrons, "color");
+                let backgrounds = self.get_electron_colors(&electrons, "background-color");
+
+                for (color_electron, color) in &colors {
+                    for (background_electron, background) in &backgrounds {
+                        // translucent text is blended over its background
+                        let color = color.mix(background, color.a).with_alpha(1.0);
+                        let ratio = color.contrast_ratio(background);
+                        if ratio < level.required_ratio() {
+                            issues.push(ContrastIssue {
+                                molecule: molecule_name.to_owned(),
+                                atom: atom_name.to_owned(),
+                                color: color_electron.name.to_owned(),
+                                background: background_electron.name.to_owned(),
+                                ratio,
+                                level,
+                            });
+                        }
+                    }
+                }
+            }
+        }
+
+        issues
+    }
+
+    /// Reports every atom applying electrons which set the same property, along
+    /// with the electron that wins once conflicts are resolved. Exports must be
+    /// up to date.
+    pub fn check_conflicts(&self) -> Vec<ElectronConflict> {
+        let mut conflicts = vec![];
+
+        let mut molecule_names: Vec<&MoleculeName> = self.molecules.keys().collect();
+        molecule_names.sort();
+
+        for molecule_name in molecule_names {
+            let molecule = &self.molecules[molecule_name];
+            let mut atom_names: Vec<&AtomName> = molecule.atoms.keys().collect();
+            atom_names.sort();
+
+            for atom_name in atom_names {
+                let Ok(classes) = self.get_atom_classes(molecule, atom_name) else {
+                    continue;
+                };
+                let resolved = self.resolve_electron_conflicts(&classes);
+
+                let mut groups: Vec<(ElectronReference, String, Vec<String>)> = vec![];
+                for class in &classes {
+                    let Some((reference, electron)) = self.get_referenced_electron(class) else {
+                        continue;
+                    };
+                    for declaration in &electron.declarations {
+                        let group = groups.iter_mut().find(|(r, property, _)| {
+                            r.condition == reference.condition
+                                && r.variants == reference.variants
+                                && *property == declaration.property
+                        });
+                        match group {
+                            Some((_, _, electrons)) if electrons.contains(class) => {}
+                            Some((_, _, electrons)) => electrons.push(class.to_owned()),
+                            None => groups.push((
+                                reference.clone(),
+                                declaration.property.to_owned(),
+                                vec![class.to_owned()],
+                            )),
+                        }
+                    }
+                }
+
+                for (_, property, electrons) in groups {
+                    if electrons.len() < 2 {
+                        continue;
+                    }
+                    // electrons kept by the resolution are ordered by the stylesheet
+                    let winner = electrons
+                        .iter()
+                        .filter(|class| resolved.contains(class))
+                        .max_by_key(|class| {
+                            self.get_referenced_electron(class)
+                                .and_then(|(reference, _)| {
+                                    self.get_electron_order()
+                                        .iter()
+                                        .position(|name| *name == reference.electron)
+                                })
+                        })
+                        .cloned()
+                        .unwrap_or_default();
+                    conflicts.push(ElectronConflict {
+                        molecule: molecule_name.to_owned(),
+                        atom: atom_name.to_owned(),
+                        property,
+                        electrons,
+                        winner,
+                    });
+                }
+            }
+        }
+
+        conflicts
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn organism() -> Organism {
+        Organism::new()
+            .with_electrons(vec![
+                Electron::new("red", "color", "#ff0000"),
+                Electron::new("black", "color", "#000000"),
+                Electron::new("gray", "color", "#767676"),
+                Electron::new("bg_green", "background-color", "#00ff00"),
+                Electron::new("bg_white", "background-color", "#ffffff"),
+            ])
+            .with_molecules(vec![
+                Molecule::new("button").with_atom(Atom::new("label").with_electrons(vec!["gray"])),
+                Molecule::new("flag")
+                    .with_atom(Atom::new("root").with_electrons(vec!["red", "bg_green"]))
+                    .with_atom(Atom::new("ok").with_electrons(vec!["black", "bg_white"]))
+                    .with_atom(
+                        Atom::new("label")
+                            .with_electrons(vec!["bg_white"])
+                            .with_imports(vec![("button", "label")]),
+                    ),
+            ])
+    }
+
+    #[test]
+    fn it_reports_aa_failures() {
+        let issues = organism().check_contrast(ContrastLevel::AA);
+        assert_eq!(issues.len(), 1);
+        assert_eq!(
+            issues[0].to_string(),
+            "flag.root: `red` on `bg_green` has a contrast ratio of 2.91:1, AA requires 4.5:1"
+        );
+    }
+
+    #[test]
+    fn it_resolves_imported_electrons() {
+        let issues = organism().check_contrast(ContrastLevel::AAA);
+        let failing: Vec<(&str, &str)> = issues
+            .iter()
+            .map(|issue| (issue.molecule.as_str(), issue.atom.as_str()))
+            .collect();
+        assert_eq!(failing, vec![("flag", "label"), ("flag", "root")]);
+    }
+
+    #[test]
+    fn it_reports_conflicts() {
+        let mut organism = organism().with_molecule(
+            Molecule::new("badge").with_atom(
+                Atom::new("root")
+                    .with_imports(vec![("button", "label")])
+                    .with_electrons(vec!["red", "hover:red", "hover:black", "bg_white"]),
+            ),
+        );
+        organism.update_exports().unwrap();
+
+        let conflicts: Vec<String> = organism
+            .check_conflicts()
+            .iter()
+            .map(|conflict| conflict.to_string())
+            .collect();
+        assert_eq!(
+            conflicts,
+            vec![
+                "badge.root: `gray`, `red` all set `color`, `red` wins",
+                "badge.root: `hover:red`, `hover:black` all set `color`, `hover:black` wins",
+            ]
+        );
+    }
+
+    #[test]
+    fn it_reports_partial_conflicts() {
+        let mut organism = Organism::new()
+            .with_electron(Electron::new("clip", "overflow", "clip"))
+            .with_electron(
+                Electron::empty("truncate")
+                    .with_declaration(CSSDeclaration::new("overflow", "hidden"))
+                    .with_declaration(CSSDeclaration::new("white-space", "nowrap")),
+            )
+            .with_molecule(
+                Molecule::new("card")
+                    .with_atom(Atom::new("title").with_electrons(vec!["truncate", "clip"])),
+            );
+
+        // `truncate` still sets `white-space`, and is later in the stylesheet
+        assert_eq!(
+            organism.get_exports()["card"]["title"],
+            vec!["truncate", "clip"]
+        );
+        let conflicts: Vec<String> = organism
+            .check_conflicts()
+            .iter()
+            .map(|conflict| conflict.to_string())
+            .collect();
+        assert_eq!(
+            conflicts,
+            vec!["card.title: `truncate`, `clip` all set `overflow`, `truncate` wins"]
+        );
+    }
+
+    #[test]
+    fn it_reports_specificity_issues() {
+        let organism = Organism::new().with_molecule(
+            Molecule::new("card")
+                .with_atom(Atom::new("root"))
+                .with_css_rule(
+                    CSSRule::new("${root}")
+                        .with_declaration(CSSDeclaration::new("padding", "1rem"))
+                        .with_rule(
+                            CSSRule::new("&:hover:focus")
+                                .with_declaration(CSSDeclaration::new("color", "red !important")),
+                        )
+                        .with_rule(
+                            CSSRule::new(":not(&) span")
+                                .with_declaration(CSSDeclaration::new("color", "blue")),
+                        )
+                        .with_at_rule(
+                            CSSAtRule::new("media", Some("print"))
+                                .with_rule(CSSRule::new("a b c").with_rule(CSSRule::new("#x"))),
+                        ),
+                ),
+        );
+
+        let issues: Vec<String> = organism
+            .check_specificity(&SpecificityBudget {
+                max_specificity: Specificity::new(0, 2, 0),
+                max_descendant_depth: 2,
+            })
+            .iter()
+            .map(|issue| issue.to_string())
+            .collect();
+        assert_eq!(
+            issues,
+            vec![
+                "card: `${root} &:hover:focus` has a specificity of (0,3,0), the budget is (0,2,0)",
+                "card: `${root} &:hover:focus` uses `!important` for `color`",
+                "card: `${root} a b c` has 3 descendant combinators, the limit is 2",
+                "card: `${root} a b c #x` uses an id",
+                "card: `${root} a b c #x` has a specificity of (1,1,3), the budget is (0,2,0)",
+                "card: `${root} a b c #x` has 4 descendant combinators, the limit is 2",
+            ]
+        );
+    }
+
+    #[test]
+    fn it_parses_levels() {
+        assert_eq!("aa".parse(), Ok(ContrastLevel::AA));
+        assert_eq!("AAA".parse(), Ok(ContrastLevel::AAA));
+        assert!("A".parse::<ContrastLevel>().is_err());
+    }
+}
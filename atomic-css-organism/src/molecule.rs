@@ -1,29 +1,182 @@
 use derive_more::Display;
-use regex::Regex;
+use regex::{Captures, Regex};
 use std::collections::{HashMap, HashSet};
+use std::fmt;
 
 use crate::atom::*;
 use crate::css::*;
 use crate::electron::*;
+use crate::prop::*;
+use crate::selector::*;
+use crate::visitor::*;
+
+fn get_placeholder_regex() -> Regex {
+    Regex::new(r"\\?\$\{([^}]*)\}").unwrap()
+}
+
+/// Whether the byte `offset` of `text` is within a quoted string, e.g. an
+/// attribute value or a `content`, where placeholders are left as written.
+fn is_quoted(text: &str, offset: usize) -> bool {
+    let mut quote = None;
+    let mut escaped = false;
+    for c in text[..offset].chars() {
+        match (quote, c) {
+            _ if escaped => escaped = false,
+            (_, '\\') => escaped = true,
+            (None, '"' | '\'') => quote = Some(c),
+            (Some(q), c) if c == q => quote = None,
+            _ => {}
+        }
+    }
+    quote.is_some()
+}
 
 fn template_string(template: &str, values: &HashMap<String, String>) -> String {
-    let re = Regex::new(r"\$\{([^}]+)\}").unwrap();
-    let mut result = template.to_string();
-    for cap in re.captures_iter(template) {
-        let key = cap.get(1).unwrap().as_str();
-        let value = values.get(key).unwrap();
-        result = result.replace(&format!("${{{}}}", key), value);
+    get_placeholder_regex()
+        .replace_all(template, |cap: &Captures| {
+            let placeholder = &cap[0];
+            if let Some(escaped) = placeholder.strip_prefix('\\') {
+                return escaped.to_string();
+            }
+            if is_quoted(template, cap.get(0).unwrap().start()) {
+                return placeholder.to_string();
+            }
+            // unknown placeholders are left untouched
+            match values.get(&cap[1]) {
+                Some(value) => value.to_owned(),
+                None => placeholder.to_string(),
+            }
+        })
+        .to_string()
+}
+
+/// Resolves the atom references of a selector through the selector model,
+/// falling back to `template_string` for the selectors it cannot resolve,
+/// e.g. referencing unknown atoms.
+fn resolve_selector(selector: &str, values: &HashMap<String, String>) -> String {
+    match SelectorList::parse(selector) {
+        Ok(selectors) if !selectors.get_atom_references().is_empty() => selectors
+            .resolve_atom_references(values)
+            .map(|resolved| resolved.to_string())
+            .unwrap_or_else(|_| template_string(selector, values)),
+        _ => template_string(selector, values),
+    }
+}
+
+/// Resolves the placeholders of a tree, see `Molecule::get_nodes_with_references`.
+struct TemplateResolver<'a>(&'a HashMap<String, String>);
+
+impl VisitorMut for TemplateResolver<'_> {
+    fn visit_rule_mut(&mut self, rule: &mut CSSRule) {
+        rule.selector = resolve_selector(&rule.selector, self.0);
+        walk_rule_mut(self, rule);
     }
-    result
+
+    fn visit_at_rule_mut(&mut self, at_rule: &mut CSSAtRule) {
+        at_rule.params = at_rule
+            .params
+            .as_deref()
+            .map(|params| template_string(params, self.0));
+        walk_at_rule_mut(self, at_rule);
+    }
+
+    fn visit_declaration_mut(&mut self, declaration: &mut CSSDeclaration) {
+        declaration.property = template_string(&declaration.property, self.0);
+        declaration.value = template_string(&declaration.value, self.0);
+    }
+}
+
+/// Placeholders of a template along with their byte offset in it, leaving
+/// out the escaped and quoted ones, i.e. `\${` and `"${"`.
+fn get_placeholders(template: &str) -> Vec<(usize, String)> {
+    get_placeholder_regex()
+        .captures_iter(template)
+        .filter(|cap| !cap[0].starts_with('\\'))
+        .filter(|cap| !is_quoted(template, cap.get(0).unwrap().start()))
+        .map(|cap| (cap.get(0).unwrap().start(), cap[1].to_string()))
+        .collect()
+}
+
+/// A `${atom}` placeholder which does not match any atom of the molecule,
+/// along with the selector, parameters or declaration it was found in and
+/// the span of its rule or declaration in the source, if any.
+#[derive(Clone, Eq, PartialEq, Debug)]
+pub struct PlaceholderError {
+    pub molecule: MoleculeName,
+    pub placeholder: String,
+    pub template: String,
+    pub span: Option<Span>,
+    pub available: Vec<AtomName>,
 }
 
-fn get_variables(str: &str) -> Vec<String> {
-    let re = Regex::new(r"\$\{([^}]+)\}").unwrap();
-    let mut variables = Vec::new();
-    for cap in re.captures_iter(str) {
-        variables.push(cap.get(1).unwrap().as_str().to_string());
+impl fmt::Display for PlaceholderError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "Unknown atom `{}` in `{}`",
+            self.placeholder, self.template
+        )?;
+        if let Some(span) = &self.span {
+            write!(f, " at {:?}", span)?;
+        }
+        write!(
+            f,
+            " in molecule `{}`, available atoms: {}",
+            self.molecule,
+            self.available.join(", ")
+        )
+    }
+}
+
+/// Finds the first unknown placeholder of a tree, see
+/// `Molecule::check_placeholders`.
+struct PlaceholderChecker<'a> {
+    molecule: &'a Molecule,
+    error: Option<PlaceholderError>,
+}
+
+impl PlaceholderChecker<'_> {
+    fn check(&mut self, template: &str, span: &Option<Span>) {
+        if self.error.is_some() {
+            return;
+        }
+        for (_, placeholder) in get_placeholders(template) {
+            if parse_atom_reference(&placeholder).is_some()
+                || self.molecule.atoms.contains_key(&placeholder)
+            {
+                continue;
+            }
+            let mut available: Vec<AtomName> = self.molecule.atoms.keys().cloned().collect();
+            available.sort();
+            self.error = Some(PlaceholderError {
+                molecule: self.molecule.name.to_owned(),
+                placeholder,
+                template: template.to_owned(),
+                span: span.to_owned(),
+                available,
+            });
+            return;
+        }
+    }
+}
+
+impl Visitor for PlaceholderChecker<'_> {
+    fn visit_rule(&mut self, rule: &CSSRule) {
+        self.check(&rule.selector, &rule.span);
+        walk_rule(self, rule);
+    }
+
+    fn visit_at_rule(&mut self, at_rule: &CSSAtRule) {
+        if let Some(params) = &at_rule.params {
+            self.check(params, &at_rule.span);
+        }
+        walk_at_rule(self, at_rule);
+    }
+
+    fn visit_declaration(&mut self, declaration: &CSSDeclaration) {
+        self.check(&declaration.property, &declaration.span);
+        self.check(&declaration.value, &declaration.span);
     }
-    variables
 }
 
 #[derive(Clone, Eq, PartialEq, Debug, Default)]
@@ -72,14 +225,35 @@ impl HashedAtoms {
 
 pub type MoleculeName = String;
 
+/// A `${molecule.atom}` placeholder referencing an atom of another molecule,
+/// along with the local atoms interpolated in the same rule, which depend on
+/// it.
+#[derive(Clone, Eq, PartialEq, Debug, Display)]
+#[display(fmt = "{}.{}", molecule, atom)]
+pub struct AtomReference {
+    pub molecule: MoleculeName,
+    pub atom: AtomName,
+    pub dependents: Vec<AtomName>,
+}
+
+/// Splits a `molecule.atom` placeholder.
+pub fn parse_atom_reference(placeholder: &str) -> Option<(&str, &str)> {
+    placeholder
+        .split_once('.')
+        .filter(|(molecule, atom)| !molecule.is_empty() && !atom.is_empty())
+}
+
 #[derive(Clone, Eq, PartialEq, Debug, Default, Display)]
 #[display(fmt = "#Molecule({})", name)]
 pub struct Molecule {
     pub name: MoleculeName,
     dependencies: HashSet<MoleculeName>,
     pub atoms: HashMap<AtomName, Atom>,
+    pub props: Vec<Prop>,
+    pub compounds: Vec<CompoundProp>,
+    pub references: Vec<AtomReference>,
     hashed_atoms: HashedAtoms,
-    css: String,
+    nodes: Vec<CSSNode>,
 }
 
 impl Molecule {
@@ -136,23 +310,213 @@ impl Molecule {
         self.hashed_atoms.update_atom_selector(atom);
     }
 
+    pub fn with_prop(mut self, prop: Prop) -> Self {
+        self.insert_prop(&prop);
+        self
+    }
+
+    pub fn with_compound(mut self, compound: CompoundProp) -> Self {
+        self.insert_compound(&compound);
+        self
+    }
+
+    /// Generates an atom per prop value and atom, see `get_prop_atom_name`.
+    /// Props declared more than once are merged.
+    pub fn insert_prop(&mut self, prop: &Prop) {
+        for value in &prop.values {
+            let selection = vec![(prop.name.to_owned(), value.value.to_owned())];
+            for atom in &value.atoms {
+                self.insert_prop_atom(atom, &selection);
+            }
+        }
+        match self.props.iter_mut().find(|p| p.name == prop.name) {
+            Some(existing) => {
+                for value in &prop.values {
+                    existing.insert_value(value);
+                }
+            }
+            None => self.props.push(prop.clone()),
+        }
+    }
+
+    pub fn insert_compound(&mut self, compound: &CompoundProp) {
+        for atom in &compound.atoms {
+            self.insert_prop_atom(atom, &compound.selection);
+        }
+        self.compounds.push(compound.clone());
+    }
+
+    fn insert_prop_atom(&mut self, atom: &Atom, selection: &[(PropName, String)]) {
+        let name = get_prop_atom_name(&atom.name, selection);
+        let mut prop_atom = self
+            .atoms
+            .get(&name)
+            .cloned()
+            .unwrap_or_else(|| Atom::new(&name));
+        prop_atom.electrons.extend(atom.electrons.iter().cloned());
+        prop_atom.imports.extend(atom.imports.iter().cloned());
+        prop_atom.extends.extend(atom.extends.iter().cloned());
+        self.insert_atom(&prop_atom);
+    }
+
+    /// Every combination of prop values, in declaration order.
+    pub fn get_prop_selections(&self) -> Vec<Vec<(PropName, String)>> {
+        let mut selections = vec![vec![]];
+        for prop in &self.props {
+            let mut next_selections = vec![];
+            for selection in &selections {
+                for value in &prop.values {
+                    let mut next_selection: Vec<(PropName, String)> = selection.clone();
+                    next_selection.push((prop.name.to_owned(), value.value.to_owned()));
+                    next_selections.push(next_selection);
+                }
+            }
+            selections = next_selections;
+        }
+        selections
+    }
+
+    /// Atoms declared directly, leaving out the ones generated for props.
+    pub fn get_base_atoms(&self) -> Vec<&AtomName> {
+        let mut prop_atoms = HashSet::new();
+        for prop in &self.props {
+            for value in &prop.values {
+                let selection = vec![(prop.name.to_owned(), value.value.to_owned())];
+                for atom in &value.atoms {
+                    prop_atoms.insert(get_prop_atom_name(&atom.name, &selection));
+                }
+            }
+        }
+        for compound in &self.compounds {
+            for atom in &compound.atoms {
+                prop_atoms.insert(get_prop_atom_name(&atom.name, &compound.selection));
+            }
+        }
+
+        let mut atoms: Vec<&AtomName> = self
+            .atoms
+            .keys()
+            .filter(|name| !prop_atoms.contains(*name))
+            .collect();
+        atoms.sort();
+        atoms
+    }
+
     fn update_hashable_contents_from_css(&mut self, css: &str) {
-        let variables = get_variables(css);
-        for variable in variables {
+        for (_, variable) in get_placeholders(css) {
             if let Some(atom) = self.atoms.get(&variable) {
                 self.hashed_atoms.update_atom_hashable_contents(atom, css);
             }
         }
     }
 
+    fn update_references_from_css(&mut self, css: &str) {
+        let placeholders = get_placeholders(css);
+        let dependents: Vec<AtomName> = placeholders
+            .iter()
+            .filter(|(_, placeholder)| self.atoms.contains_key(placeholder))
+            .map(|(_, placeholder)| placeholder.to_owned())
+            .collect();
+        for (_, placeholder) in &placeholders {
+            if let Some((molecule, atom)) = parse_atom_reference(placeholder) {
+                self.references.push(AtomReference {
+                    molecule: molecule.to_string(),
+                    atom: atom.to_string(),
+                    dependents: dependents.clone(),
+                });
+            }
+        }
+    }
+
+    /// Placeholders are not checked, unknown ones being left untouched in the
+    /// output, see `check_placeholders`.
     pub fn insert_css_rule(&mut self, css_rule: &CSSRule) {
-        self.css.push_str(&css_rule.get_css());
+        self.nodes.push(CSSNode::CSSRule(css_rule.clone()));
 
         self.update_hashable_contents_from_css(&css_rule.get_css());
+        self.update_references_from_css(&css_rule.get_css());
+    }
+
+    /// Rules and at-rules of the molecule, in declaration order.
+    pub fn get_nodes(&self) -> &Vec<CSSNode> {
+        &self.nodes
+    }
+
+    /// Mutable access to the rules, e.g. for optimization passes. Atoms are
+    /// hashed on insertion, so class names do not change along with them.
+    /// Placeholders referencing other molecules are resolved from the rules
+    /// at output time, see `get_referenced_atoms`, while `references`, which
+    /// orders exports, is only updated on insertion.
+    pub fn get_nodes_mut(&mut self) -> &mut Vec<CSSNode> {
+        &mut self.nodes
+    }
+
+    /// Atoms of other molecules referenced by the current rules, as
+    /// `(placeholder, molecule, atom)`.
+    pub fn get_referenced_atoms(&self) -> Vec<(String, MoleculeName, AtomName)> {
+        let mut atoms = vec![];
+        for node in &self.nodes {
+            for (_, placeholder) in get_placeholders(&node.get_css()) {
+                if let Some((molecule, atom)) = parse_atom_reference(&placeholder) {
+                    atoms.push((placeholder.to_owned(), molecule.to_owned(), atom.to_owned()));
+                }
+            }
+        }
+        atoms
     }
 
+    /// Css of the molecule, leaving references to other molecules untouched.
     pub fn get_css(&self) -> String {
-        template_string(&self.css, &self.hashed_atoms.selectors)
+        self.get_css_with_references(&HashMap::new())
+    }
+
+    /// Css of the molecule, resolving references to other molecules with
+    /// `selectors`, keyed by `molecule.atom`.
+    pub fn get_css_with_references(&self, selectors: &HashMap<String, String>) -> String {
+        self.get_nodes_with_references(selectors)
+            .iter()
+            .map(|node| node.get_css())
+            .collect()
+    }
+
+    /// Same as `get_css_with_references`, with nested rules flattened.
+    pub fn get_flat_css_with_references(&self, selectors: &HashMap<String, String>) -> String {
+        flatten_nodes(&self.get_nodes_with_references(selectors), None)
+            .iter()
+            .map(|node| node.get_css())
+            .collect()
+    }
+
+    /// Rules of the molecule, resolving references to other molecules with
+    /// `selectors` as in `get_css_with_references`.
+    pub fn get_nodes_with_references(&self, selectors: &HashMap<String, String>) -> Vec<CSSNode> {
+        let values = self.get_template_values(selectors);
+        self.nodes
+            .iter()
+            .map(|node| {
+                let mut node = node.clone();
+                TemplateResolver(&values).visit_node_mut(&mut node);
+                node
+            })
+            .collect()
+    }
+
+    fn get_template_values(&self, selectors: &HashMap<String, String>) -> HashMap<String, String> {
+        let mut values = self.hashed_atoms.selectors.clone();
+        values.extend(selectors.iter().map(|(k, v)| (k.to_owned(), v.to_owned())));
+        values
+    }
+
+    /// Checks that every placeholder of `node` references an atom of the
+    /// molecule, as the runtime does for every rule. References to other
+    /// molecules are checked by the organism.
+    pub fn check_placeholders(&self, node: &CSSNode) -> Result<(), PlaceholderError> {
+        let mut checker = PlaceholderChecker {
+            molecule: self,
+            error: None,
+        };
+        checker.visit_node(node);
+        checker.error.map_or(Ok(()), Err)
     }
 
     pub fn get_atom_selector(&self, atom_name: &str) -> Option<&String> {
@@ -163,6 +527,10 @@ impl Molecule {
         self.atoms.get(atom_name).map(|atom| &atom.imports)
     }
 
+    pub fn get_atom_extends(&self, atom_name: &str) -> Option<&Vec<AtomName>> {
+        self.atoms.get(atom_name).map(|atom| &atom.extends)
+    }
+
     pub fn get_atom_electrons(&self, atom_name: &str) -> Option<&Vec<ElectronName>> {
         self.atoms.get(atom_name).map(|atom| &atom.electrons)
     }
@@ -172,9 +540,91 @@ impl Molecule {
             && !self.hashed_atoms.hashable_contents[atom_name].is_empty()
     }
 
+    /// Same as `insert_css_rule` for an at-rule.
     pub fn insert_css_at_rule(&mut self, css_at_rule: &CSSAtRule) {
-        self.css.push_str(&css_at_rule.get_css());
+        self.nodes.push(CSSNode::CSSAtRule(css_at_rule.clone()));
 
         self.update_hashable_contents_from_css(&css_at_rule.get_css());
+        self.update_references_from_css(&css_at_rule.get_css());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_resolves_placeholders() {
+        let values = HashMap::from([("root".to_string(), ".flag_root_12".to_string())]);
+        assert_eq!(
+            template_string("${root}:hover ${root}>\\${root}", &values),
+            ".flag_root_12:hover .flag_root_12>${root}"
+        );
+        assert_eq!(template_string("${rot}", &values), "${rot}");
+        assert_eq!(
+            get_placeholders("\\${label} ${root}"),
+            vec![(10, "root".to_string())]
+        );
+        assert_eq!(get_placeholders("[data-x=\"${root}\"]"), vec![]);
+    }
+
+    #[test]
+    fn it_resolves_selectors_through_the_selector_model() {
+        let molecule = Molecule::new("flag")
+            .with_atom(Atom::new("root"))
+            .with_css_rule(
+                CSSRule::new("[data-x=\"${root}\"] ${root}")
+                    .with_declaration(CSSDeclaration::new("content", "\"${root}\"")),
+            );
+        assert_eq!(
+            molecule.get_css(),
+            "[data-x=\"${root}\"] .flag_root_46{content:\"${root}\";}"
+        );
+    }
+
+    #[test]
+    fn it_reports_unknown_placeholders() {
+        let molecule = Molecule::new("flag")
+            .with_atom(Atom::new("root"))
+            .with_atom(Atom::new("label"));
+        let rule = CSSRule::new("${root} ${label}")
+            .with_declaration(CSSDeclaration::new("color", "red"))
+            .with_span(0..40);
+        assert_eq!(
+            molecule.check_placeholders(&CSSNode::CSSRule(rule.clone())),
+            Ok(())
+        );
+
+        let rule = rule.with_rule(
+            CSSRule::new("& > a")
+                .with_declaration(CSSDeclaration::new("border", "${rot}").with_span(20..38)),
+        );
+        assert_eq!(
+            molecule
+                .check_placeholders(&CSSNode::CSSRule(rule))
+                .unwrap_err()
+                .to_string(),
+            "Unknown atom `rot` in `${rot}` at 20..38 in molecule `flag`, \
+             available atoms: label, root"
+        );
+    }
+
+    #[test]
+    fn it_renders_nodes_at_output_time() {
+        let mut molecule = Molecule::new("card")
+            .with_atom(Atom::new("root"))
+            .with_css_rule(
+                CSSRule::new("${root}").with_declaration(CSSDeclaration::new("color", "red")),
+            )
+            .with_css_rule(CSSRule::new("${root}:hover"));
+        assert_eq!(
+            molecule.get_css(),
+            ".card_root_34{color:red;}.card_root_34:hover{}"
+        );
+
+        molecule.get_nodes_mut().retain(
+            |node| !matches!(node, CSSNode::CSSRule(rule) if rule.get_children().is_empty()),
+        );
+        assert_eq!(molecule.get_css(), ".card_root_34{color:red;}");
     }
 }
@@ -1,53 +1,55 @@
 use petgraph::algo::toposort;
-use petgraph::algo::Cycle;
 use petgraph::graph::{DiGraph, NodeIndex};
 use std::collections::{HashMap, HashSet};
 
 use crate::atom::*;
+use crate::css::*;
 use crate::electron::*;
 use crate::molecule::*;
+use crate::overlay::*;
+use crate::serializer::*;
+use crate::theme::*;
+use crate::token::*;
+use crate::variant::*;
+
+/// An atom as a node of the dependency graph.
+pub type AtomId = (MoleculeName, AtomName);
 
 #[derive(Clone, Debug, Default)]
 struct Dependencies {
-    node_ids: HashMap<MoleculeName, NodeIndex>,
-    graph: DiGraph<MoleculeName, ()>,
+    node_ids: HashMap<AtomId, NodeIndex>,
+    graph: DiGraph<AtomId, ()>,
 }
 
 impl Dependencies {
-    fn contains(&self, molecule: &MoleculeName) -> bool {
-        self.node_ids.contains_key(molecule)
+    fn contains(&self, atom: &AtomId) -> bool {
+        self.node_ids.contains_key(atom)
     }
 
-    fn add_molecule(&mut self, molecule: MoleculeName) -> NodeIndex {
-        if !self.contains(&molecule.to_owned()) {
-            let molecule_id = self.graph.add_node(molecule.to_owned());
-            self.node_ids.insert(molecule, molecule_id);
-            molecule_id
+    fn add_atom(&mut self, atom: AtomId) -> NodeIndex {
+        if !self.contains(&atom) {
+            let atom_id = self.graph.add_node(atom.to_owned());
+            self.node_ids.insert(atom, atom_id);
+            atom_id
         } else {
-            self.node_ids.get(&molecule).unwrap().to_owned()
+            self.node_ids.get(&atom).unwrap().to_owned()
         }
     }
 
-    fn add_dependency(&mut self, from: MoleculeName, to: MoleculeName) {
-        let to_id = if let Some(id) = self.node_ids.get(&from) {
-            *id
-        } else {
-            self.add_molecule(from)
-        };
-
-        let from_id = if let Some(id) = self.node_ids.get(&to) {
-            *id
-        } else {
-            self.add_molecule(to)
-        };
+    /// `from` depends on `to`, so `to` must be exported first.
+    fn add_dependency(&mut self, from: AtomId, to: AtomId) {
+        let from_id = self.add_atom(from);
+        let to_id = self.add_atom(to);
 
-        self.graph
-            .add_edge(from_id.to_owned(), to_id.to_owned(), ());
+        self.graph.update_edge(to_id, from_id, ());
     }
 
-    fn get_topological_order(&self) -> Result<Vec<MoleculeName>, Cycle<NodeIndex>> {
+    fn get_topological_order(&self) -> Result<Vec<AtomId>, String> {
         let mut result = Vec::new();
-        let nodes = toposort(&self.graph, None)?;
+        let nodes = toposort(&self.graph, None).map_err(|cycle| {
+            let (molecule, atom) = &self.graph[cycle.node_id()];
+            format!("Circular atom dependency involving `{}.{}`", molecule, atom)
+        })?;
 
         for node in &nodes {
             result.push(self.graph[*node].to_owned());
@@ -61,9 +63,15 @@ impl Dependencies {
 pub struct Organism {
     pub electrons: HashMap<ElectronName, Electron>,
     pub molecules: HashMap<MoleculeName, Molecule>,
+    pub tokens: HashMap<TokenName, Token>,
+    pub token_bindings: HashMap<(ElectronName, String), TokenName>,
+    pub themes: Vec<Theme>,
+    pub overlays: Vec<Overlay>,
+    pub conditions: Vec<Condition>,
+    electron_order: Vec<ElectronName>,
     dependencies: Dependencies,
     exports: HashMap<MoleculeName, HashMap<AtomName, Vec<String>>>,
-    css: String,
+    molecule_order: Vec<MoleculeName>,
 }
 
 impl Organism {
@@ -97,82 +105,475 @@ impl Organism {
         self
     }
 
+    pub fn with_token(mut self, token: Token) -> Self {
+        self.insert_token(&token);
+        self
+    }
+
+    pub fn with_tokens(mut self, tokens: Vec<Token>) -> Self {
+        for token in tokens {
+            self.insert_token(&token);
+        }
+        self
+    }
+
+    pub fn insert_token(&mut self, token: &Token) {
+        self.tokens.insert(token.name.to_owned(), token.to_owned());
+    }
+
+    /// Records that the value of the `property` declaration of `electron`
+    /// comes from `token`.
+    pub fn bind_electron(&mut self, electron: &str, property: &str, token: &str) {
+        self.token_bindings
+            .insert((electron.to_owned(), property.to_owned()), token.to_owned());
+    }
+
+    pub fn get_token_value(&self, token: &str) -> Result<String, String> {
+        resolve_token(&self.tokens, token)
+    }
+
+    pub fn with_theme(mut self, theme: Theme) -> Self {
+        self.insert_theme(&theme);
+        self
+    }
+
+    pub fn insert_theme(&mut self, theme: &Theme) {
+        self.themes.retain(|t| t.name != theme.name);
+        self.themes.push(theme.to_owned());
+    }
+
+    /// Custom properties holding the token defaults, followed by the
+    /// overrides of every theme.
+    fn get_tokens_nodes(&self) -> Vec<CSSNode> {
+        let mut nodes = vec![];
+
+        if !self.tokens.is_empty() {
+            let mut tokens: Vec<&Token> = self.tokens.values().collect();
+            tokens.sort_by(|a, b| a.name.cmp(&b.name));
+            nodes.push(CSSNode::CSSRule(get_custom_properties_rule(
+                ":root", tokens,
+            )));
+        }
+
+        for theme in &self.themes {
+            nodes.extend(theme.get_nodes());
+        }
+
+        nodes
+    }
+
+    /// Electron references with variants or a condition used by any atom,
+    /// ordered by variant and then by electron declaration order.
+    fn get_variant_references(&self) -> Vec<ElectronReference> {
+        let mut references = HashSet::new();
+        for molecule in self.molecules.values() {
+            for atom in molecule.atoms.values() {
+                for electron in &atom.electrons {
+                    match ElectronReference::parse(electron) {
+                        Ok(reference)
+                            if !reference.variants.is_empty() || reference.condition.is_some() =>
+                        {
+                            references.insert(reference);
+                        }
+                        _ => {}
+                    }
+                }
+            }
+        }
+
+        let mut references: Vec<ElectronReference> = references.into_iter().collect();
+        references.sort_by_key(|reference| {
+            (
+                reference.variants.clone(),
+                self.electron_order
+                    .iter()
+                    .position(|name| *name == reference.electron),
+            )
+        });
+        references
+    }
+
+    /// Variant rules, followed by the conditional ones wrapped in an at-rule
+    /// per condition, in condition declaration order so that breakpoints
+    /// declared later take precedence.
+    fn get_variants_nodes(&self) -> Vec<CSSNode> {
+        let references = self.get_variant_references();
+
+        let mut nodes: Vec<CSSNode> = references
+            .iter()
+            .filter(|reference| reference.condition.is_none())
+            .filter_map(|reference| {
+                let electron = self.electrons.get(&reference.electron)?;
+                Some(CSSNode::CSSRule(electron.get_variant_rule(reference)))
+            })
+            .collect();
+
+        for condition in &self.conditions {
+            let rules: Vec<CSSRule> = references
+                .iter()
+                .filter(|reference| reference.condition.as_ref() == Some(&condition.name))
+                .filter_map(|reference| {
+                    let electron = self.electrons.get(&reference.electron)?;
+                    Some(electron.get_variant_rule(reference))
+                })
+                .collect();
+
+            if !rules.is_empty() {
+                let mut at_rule = condition.get_at_rule();
+                for rule in &rules {
+                    at_rule.insert_rule(rule);
+                }
+                nodes.push(CSSNode::CSSAtRule(at_rule));
+            }
+        }
+
+        nodes
+    }
+
+    /// Selectors of the atoms referenced across molecules, keyed by
+    /// `molecule.atom`, including the references added to the nodes of a
+    /// molecule after its insertion.
+    fn get_reference_selectors(&self) -> HashMap<String, String> {
+        let mut selectors = HashMap::new();
+        for molecule in self.molecules.values() {
+            for (placeholder, molecule_name, atom_name) in molecule.get_referenced_atoms() {
+                let selector = self
+                    .molecules
+                    .get(&molecule_name)
+                    .and_then(|m| m.get_atom_selector(&atom_name));
+                if let Some(selector) = selector {
+                    selectors.insert(placeholder, selector.to_owned());
+                }
+            }
+        }
+        selectors
+    }
+
+    pub fn get_css(&self) -> String {
+        self.serialize(&CompactSerializer, false)
+    }
+
+    /// Same as `get_css` without css nesting, for older browsers. Class names
+    /// are the same in both outputs.
+    pub fn get_flat_css(&self) -> String {
+        self.serialize(&CompactSerializer, true)
+    }
+
+    /// The stylesheet of `get_css`, or of `get_flat_css` when `flatten` is
+    /// set, written by `serializer`.
+    pub fn serialize(&self, serializer: &dyn Serializer, flatten: bool) -> String {
+        self.write(serializer, flatten).css
+    }
+
+    /// Same as `serialize`, along with the spans the css was written from.
+    /// Molecules are rendered in declaration order, once every molecule has
+    /// been hashed, so that they can reference each other.
+    pub fn write(&self, serializer: &dyn Serializer, flatten: bool) -> Output {
+        let mut output = Output::default();
+        serializer.write_nodes(&self.get_tokens_nodes(), 0, &mut output);
+
+        for electron in self
+            .electron_order
+            .iter()
+            .filter_map(|name| self.electrons.get(name))
+        {
+            serializer.write_electron_rule(&electron.get_rule(), &mut output);
+        }
+        for node in self.get_variants_nodes() {
+            match &node {
+                CSSNode::CSSRule(rule) => serializer.write_electron_rule(rule, &mut output),
+                _ => serializer.write_node(&node, 0, &mut output),
+            }
+        }
+
+        let selectors = self.get_reference_selectors();
+        let mut nodes = vec![];
+        for molecule in self
+            .molecule_order
+            .iter()
+            .filter_map(|name| self.molecules.get(name))
+        {
+            let molecule_nodes = molecule.get_nodes_with_references(&selectors);
+            match flatten {
+                true => nodes.extend(flatten_nodes(&molecule_nodes, None)),
+                false => nodes.extend(molecule_nodes),
+            }
+        }
+        serializer.write_nodes(&nodes, 0, &mut output);
+
+        output
+    }
+
+    /// Checks that the atoms referenced across molecules exist.
+    pub fn check_references(&self) -> Result<(), String> {
+        let mut molecule_names: Vec<&MoleculeName> = self.molecules.keys().collect();
+        molecule_names.sort();
+        for molecule_name in molecule_names {
+            for reference in &self.molecules[molecule_name].references {
+                let exists = self
+                    .molecules
+                    .get(&reference.molecule)
+                    .is_some_and(|m| m.atoms.contains_key(&reference.atom));
+                if !exists {
+                    return Err(format!(
+                        "Unknown atom `{}` referenced by molecule `{}`",
+                        reference, molecule_name
+                    ));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn is_referenced(&self, molecule_name: &str, atom_name: &str) -> bool {
+        self.molecules.values().any(|molecule| {
+            molecule
+                .references
+                .iter()
+                .any(|r| r.molecule == molecule_name && r.atom == atom_name)
+        })
+    }
+
+    pub fn with_condition(mut self, condition: Condition) -> Self {
+        self.insert_condition(&condition);
+        self
+    }
+
+    pub fn insert_condition(&mut self, condition: &Condition) {
+        self.conditions.retain(|c| c.name != condition.name);
+        self.conditions.push(condition.to_owned());
+    }
+
+    pub fn get_condition(&self, name: &str) -> Option<&Condition> {
+        self.conditions.iter().find(|c| c.name == name)
+    }
+
+    pub fn with_overlay(mut self, overlay: Overlay) -> Self {
+        self.insert_overlay(&overlay);
+        self
+    }
+
+    pub fn insert_overlay(&mut self, overlay: &Overlay) {
+        self.overlays.retain(|o| o.name != overlay.name);
+        self.overlays.push(overlay.to_owned());
+    }
+
+    /// Builds a copy of the organism using the electron and token values of
+    /// `overlay`. Electrons bound to an overridden token follow its new value.
+    pub fn apply_overlay(&self, overlay: &Overlay) -> Result<Organism, String> {
+        let mut organism = self.clone();
+
+        for token in &overlay.tokens {
+            if !organism.tokens.contains_key(&token.name) {
+                return Err(format!(
+                    "Overlay {} overrides unknown token {}",
+                    overlay.name, token.name
+                ));
+            }
+            organism.insert_token(token);
+        }
+
+        for ((electron_name, property), token) in &self.token_bindings {
+            let value = organism.get_token_value(token)?;
+            if let Some(electron) = organism.electrons.get_mut(electron_name) {
+                for declaration in &mut electron.declarations {
+                    if declaration.property == *property {
+                        declaration.value = value.clone();
+                    }
+                }
+            }
+        }
+
+        for electron in &overlay.electrons {
+            if !organism.electrons.contains_key(&electron.name) {
+                return Err(format!(
+                    "Overlay {} overrides unknown electron {}",
+                    overlay.name, electron.name
+                ));
+            }
+            organism.insert_electron(electron);
+        }
+
+        Ok(organism)
+    }
+
     pub fn insert_electron(&mut self, electron: &Electron) {
+        if !self.electrons.contains_key(&electron.name) {
+            self.electron_order.push(electron.name.to_owned());
+        }
         self.electrons
             .insert(electron.name.to_owned(), electron.to_owned());
-        self.css.push_str(&electron.get_css());
     }
 
     pub fn insert_molecule(&mut self, molecule: &Molecule) {
         self.molecules
             .insert(molecule.name.to_owned(), molecule.to_owned());
-        self.css.push_str(&molecule.get_css());
-        self.dependencies.add_molecule(molecule.name.clone());
+        if !self.molecule_order.contains(&molecule.name) {
+            self.molecule_order.push(molecule.name.to_owned());
+        }
 
         for atom in molecule.atoms.values() {
-            for (molecule_name, _) in &atom.imports {
+            let atom_id = (molecule.name.to_owned(), atom.name.to_owned());
+            self.dependencies.add_atom(atom_id.to_owned());
+            for import in &atom.imports {
                 self.dependencies
-                    .add_dependency(molecule.name.to_owned(), molecule_name.to_owned());
+                    .add_dependency(atom_id.to_owned(), import.to_owned());
+            }
+            for extended_atom in &atom.extends {
+                self.dependencies.add_dependency(
+                    atom_id.to_owned(),
+                    (molecule.name.to_owned(), extended_atom.to_owned()),
+                );
             }
         }
-    }
 
-    fn get_molecule_exports(&self, molecule: MoleculeName) -> HashMap<AtomName, Vec<String>> {
-        let mut exports = HashMap::new();
+        for reference in &molecule.references {
+            let referenced_id = (reference.molecule.to_owned(), reference.atom.to_owned());
+            self.dependencies.add_atom(referenced_id.to_owned());
+            for dependent in &reference.dependents {
+                self.dependencies.add_dependency(
+                    (molecule.name.to_owned(), dependent.to_owned()),
+                    referenced_id.to_owned(),
+                );
+            }
+        }
+    }
 
-        let Some(molecule) = self.molecules.get(&molecule) else {
-            return exports;
-        };
+    /// Electrons in declaration order, which is also their stylesheet order.
+    pub fn get_electron_order(&self) -> &[ElectronName] {
+        &self.electron_order
+    }
 
-        for atom_name in molecule.atoms.keys() {
-            let mut atom_classes = HashSet::new();
+    /// Looks up the electron referenced by an exported class, e.g. `red` for
+    /// `hover:red`.
+    pub fn get_referenced_electron(&self, class: &str) -> Option<(ElectronReference, &Electron)> {
+        let reference = ElectronReference::parse(class).ok()?;
+        let electron = self.electrons.get(&reference.electron)?;
+        Some((reference, electron))
+    }
 
-            if let Some(selector) = molecule.get_atom_selector(atom_name) {
-                if molecule.has_hashable_content(atom_name) {
-                    atom_classes.insert(&selector[1..]);
-                }
+    /// Classes applied to an atom, in precedence order: its own class, the
+    /// classes of the extended atoms, the classes of the imported atoms in
+    /// import order, then its own electrons. Extended and imported atoms must
+    /// already be exported.
+    pub fn get_atom_classes(
+        &self,
+        molecule: &Molecule,
+        atom_name: &str,
+    ) -> Result<Vec<String>, String> {
+        let mut atom_classes = vec![];
+
+        if let Some(selector) = molecule.get_atom_selector(atom_name) {
+            // referenced atoms need their class even without rules of their own
+            if molecule.has_hashable_content(atom_name)
+                || self.is_referenced(&molecule.name, atom_name)
+            {
+                atom_classes.push(selector[1..].to_string());
             }
+        }
 
-            if let Some(electrons) = molecule.get_atom_electrons(atom_name) {
-                for electron in electrons {
-                    // TODO: electron hasing
-                    atom_classes.insert(electron);
+        if let Some(extends) = molecule.get_atom_extends(atom_name) {
+            for extended_atom in extends {
+                if !molecule.atoms.contains_key(extended_atom) {
+                    return Err(format!(
+                        "Unknown atom `{}` in molecule `{}`",
+                        extended_atom, molecule.name
+                    ));
+                }
+                if let Some(classes) = self.get_exported_classes(&molecule.name, extended_atom) {
+                    atom_classes.extend(classes.iter().cloned());
                 }
             }
+        }
 
-            let Some(imports) = molecule.get_atom_imports(atom_name) else {
-                continue;
-            };
-
+        if let Some(imports) = molecule.get_atom_imports(atom_name) {
             for (molecule_name, atom_name) in imports {
                 // for this to exist this function mus tbe called respecting the
                 // topological order of the dependencies
-                let Some(molecule_exports) = self.exports.get(molecule_name) else {
-                    continue;
-                };
-                if let Some(classes) = molecule_exports.get(atom_name) {
-                    for class in classes {
-                        atom_classes.insert(class);
-                    }
+                if let Some(classes) = self.get_exported_classes(molecule_name, atom_name) {
+                    atom_classes.extend(classes.iter().cloned());
                 }
             }
+        }
 
-            exports.insert(
-                atom_name.to_string(),
-                atom_classes.iter().map(|class| class.to_string()).collect(),
-            );
+        if let Some(electrons) = molecule.get_atom_electrons(atom_name) {
+            // TODO: electron hasing
+            atom_classes.extend(electrons.iter().cloned());
         }
 
-        exports
+        Ok(atom_classes)
     }
 
-    pub fn update_exports(&mut self) -> Result<(), Cycle<NodeIndex>> {
-        let molecules = self.dependencies.get_topological_order()?;
-        for molecule_name in &molecules {
-            self.exports.insert(
-                molecule_name.to_string(),
-                self.get_molecule_exports(molecule_name.to_owned()),
-            );
+    /// Classes exported by an atom, as of the last `update_exports`.
+    pub fn get_exported_classes(
+        &self,
+        molecule_name: &str,
+        atom_name: &str,
+    ) -> Option<&Vec<String>> {
+        self.exports.get(molecule_name)?.get(atom_name)
+    }
+
+    /// Drops duplicated classes and the electrons whose every declaration is
+    /// overridden by electrons with the same condition and variants coming
+    /// later in `classes`, so that the last electron wins regardless of the
+    /// stylesheet order. Electrons only partly overridden are kept, their
+    /// shared properties then following the stylesheet order, see
+    /// `check_conflicts`.
+    pub fn resolve_electron_conflicts(&self, classes: &[String]) -> Vec<String> {
+        let mut overridden = HashSet::new();
+        let mut resolved: Vec<String> = vec![];
+
+        for class in classes.iter().rev() {
+            if resolved.contains(class) {
+                continue;
+            }
+            if let Some((reference, electron)) = self.get_referenced_electron(class) {
+                let properties: Vec<_> = electron
+                    .declarations
+                    .iter()
+                    .map(|declaration| {
+                        (
+                            reference.condition.clone(),
+                            reference.variants.clone(),
+                            declaration.property.clone(),
+                        )
+                    })
+                    .collect();
+                let is_overridden = !properties.is_empty()
+                    && properties
+                        .iter()
+                        .all(|property| overridden.contains(property));
+                overridden.extend(properties);
+                if is_overridden {
+                    continue;
+                }
+            }
+            resolved.push(class.to_owned());
+        }
+
+        resolved.reverse();
+        resolved
+    }
+
+    pub fn update_exports(&mut self) -> Result<(), String> {
+        self.check_references()?;
+        let atoms = self.dependencies.get_topological_order()?;
+        for (molecule_name, atom_name) in &atoms {
+            let Some(molecule) = self.molecules.get(molecule_name) else {
+                continue;
+            };
+            if !molecule.atoms.contains_key(atom_name) {
+                continue;
+            }
+            let atom_classes = self.get_atom_classes(molecule, atom_name)?;
+            let classes = self.resolve_electron_conflicts(&atom_classes);
+            self.exports
+                .entry(molecule_name.to_owned())
+                .or_default()
+                .insert(atom_name.to_owned(), classes);
+        }
+        for molecule_name in self.molecules.keys() {
+            self.exports.entry(molecule_name.to_owned()).or_default();
         }
         Ok(())
     }
@@ -186,7 +587,6 @@ impl Organism {
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::css::*;
 
     #[test]
     fn it_works() {
@@ -225,4 +625,392 @@ mod tests {
             println!("{}: {}", molecule.name, molecule.get_css());
         }
     }
+
+    #[test]
+    fn it_generates_electron_variants_on_demand() {
+        let library = Organism::new()
+            .with_electrons(vec![
+                Electron::new("red", "color", "#ff0000"),
+                Electron::new("blue", "color", "#0000ff"),
+            ])
+            .with_molecule(
+                Molecule::new("button").with_atom(Atom::new("label").with_electrons(vec![
+                    "blue",
+                    "active:blue",
+                    "hover:red",
+                    "hover:blue",
+                    "hover:red",
+                ])),
+            );
+
+        assert_eq!(
+            library.get_css(),
+            ".red{color:#ff0000}\
+             .blue{color:#0000ff}\
+             .hover\\:red:hover{color:#ff0000}\
+             .hover\\:blue:hover{color:#0000ff}\
+             .active\\:blue:active{color:#0000ff}"
+        );
+    }
+
+    #[test]
+    fn it_generates_responsive_electrons_in_breakpoint_order() {
+        let library = Organism::new()
+            .with_condition(Condition::breakpoint("md", "(min-width: 768px)"))
+            .with_condition(Condition::breakpoint("lg", "(min-width: 1024px)"))
+            .with_electrons(vec![
+                Electron::new("red", "color", "#ff0000"),
+                Electron::new("bg_green", "background-color", "#00ff00"),
+            ])
+            .with_molecule(Molecule::new("flag").with_atom(
+                Atom::new("root").with_electrons(vec!["lg:red", "md:bg_green", "md:hover:red"]),
+            ));
+
+        assert_eq!(
+            library.get_css(),
+            ".red{color:#ff0000}\
+             .bg_green{background-color:#00ff00}\
+             @media (min-width: 768px){\
+             .md\\:bg_green{background-color:#00ff00;}\
+             .md\\:hover\\:red:hover{color:#ff0000;}}\
+             @media (min-width: 1024px){.lg\\:red{color:#ff0000;}}"
+        );
+    }
+
+    #[test]
+    fn it_generates_container_and_feature_query_electrons() {
+        let library = Organism::new()
+            .with_condition(Condition::container(
+                "card-md",
+                Some("card"),
+                "(min-width: 400px)",
+            ))
+            .with_condition(Condition::supports("grid", "(display: grid)"))
+            .with_electrons(vec![
+                Electron::new("grid", "display", "grid"),
+                Electron::new("red", "color", "#ff0000"),
+            ])
+            .with_molecule(Molecule::new("card").with_atom(
+                Atom::new("root").with_electrons(vec!["grid:grid", "card-md:hover:red"]),
+            ));
+
+        assert_eq!(
+            library.get_css(),
+            ".grid{display:grid}\
+             .red{color:#ff0000}\
+             @container card (min-width: 400px){.card-md\\:hover\\:red:hover{color:#ff0000;}}\
+             @supports (display: grid){.grid\\:grid{display:grid;}}"
+        );
+    }
+
+    #[test]
+    fn it_emits_multi_declaration_electrons() {
+        let truncate = Electron::empty("truncate")
+            .with_declaration(CSSDeclaration::new("overflow", "hidden"))
+            .with_declaration(CSSDeclaration::new("text-overflow", "ellipsis"))
+            .with_declaration(CSSDeclaration::new("white-space", "nowrap"));
+        let library = Organism::new()
+            .with_condition(Condition::breakpoint("md", "(min-width: 768px)"))
+            .with_electron(truncate)
+            .with_molecule(
+                Molecule::new("card")
+                    .with_atom(Atom::new("title").with_electrons(vec!["md:truncate"])),
+            );
+
+        assert_eq!(
+            library.get_css(),
+            ".truncate{overflow:hidden;text-overflow:ellipsis;white-space:nowrap}\
+             @media (min-width: 768px){\
+             .md\\:truncate{overflow:hidden;text-overflow:ellipsis;white-space:nowrap;}}"
+        );
+    }
+
+    #[test]
+    fn it_resolves_electron_conflicts_by_list_order() {
+        let mut library = Organism::new()
+            .with_electrons(vec![
+                Electron::new("red", "color", "#ff0000"),
+                Electron::new("blue", "color", "#0000ff"),
+                Electron::new("bg_green", "background-color", "#00ff00"),
+            ])
+            .with_molecules(vec![
+                Molecule::new("button")
+                    .with_atom(Atom::new("label").with_electrons(vec!["blue", "bg_green"])),
+                Molecule::new("flag")
+                    .with_atom(Atom::new("root").with_electrons(vec![
+                        "blue",
+                        "hover:blue",
+                        "red",
+                        "hover:red",
+                    ]))
+                    .with_atom(
+                        Atom::new("label")
+                            .with_imports(vec![("button", "label")])
+                            .with_electrons(vec!["red"]),
+                    ),
+            ]);
+
+        let exports = library.get_exports();
+        assert_eq!(exports["button"]["label"], vec!["blue", "bg_green"]);
+        assert_eq!(exports["flag"]["root"], vec!["red", "hover:red"]);
+        assert_eq!(exports["flag"]["label"], vec!["bg_green", "red"]);
+    }
+
+    #[test]
+    fn it_flattens_extended_atoms() {
+        let mut library = Organism::new()
+            .with_electrons(vec![
+                Electron::new("red", "color", "#ff0000"),
+                Electron::new("blue", "color", "#0000ff"),
+                Electron::new("bold", "font-weight", "bold"),
+            ])
+            .with_molecule(
+                Molecule::new("button")
+                    .with_atom(Atom::new("root").with_electrons(vec!["red", "bold"]))
+                    .with_atom(Atom::new("primary").with_extends(vec!["root"]))
+                    .with_atom(
+                        Atom::new("danger")
+                            .with_extends(vec!["primary"])
+                            .with_electrons(vec!["blue"]),
+                    ),
+            );
+
+        let exports = library.get_exports();
+        assert_eq!(exports["button"]["primary"], vec!["red", "bold"]);
+        assert_eq!(exports["button"]["danger"], vec!["bold", "blue"]);
+    }
+
+    #[test]
+    fn it_detects_atom_extension_cycles() {
+        let mut library = Organism::new().with_molecule(
+            Molecule::new("button")
+                .with_atom(Atom::new("root").with_extends(vec!["label"]))
+                .with_atom(Atom::new("label").with_extends(vec!["root"])),
+        );
+
+        let error = library.update_exports().unwrap_err();
+        assert!(error.starts_with("Circular atom dependency involving `button."));
+
+        let mut library = Organism::new().with_molecule(
+            Molecule::new("button").with_atom(Atom::new("root").with_extends(vec!["icon"])),
+        );
+        assert_eq!(
+            library.update_exports(),
+            Err("Unknown atom `icon` in molecule `button`".to_owned())
+        );
+    }
+
+    #[test]
+    fn it_resolves_dependencies_between_atoms() {
+        let mut library = Organism::new()
+            .with_electrons(vec![
+                Electron::new("red", "color", "#ff0000"),
+                Electron::new("bold", "font-weight", "bold"),
+            ])
+            .with_molecules(vec![
+                Molecule::new("button")
+                    .with_atom(Atom::new("root").with_electrons(vec!["red"]))
+                    .with_atom(Atom::new("label").with_imports(vec![("flag", "title")])),
+                Molecule::new("flag")
+                    .with_atom(Atom::new("title").with_electrons(vec!["bold"]))
+                    .with_atom(Atom::new("root").with_imports(vec![("button", "root")])),
+            ]);
+
+        let exports = library.get_exports();
+        assert_eq!(exports["button"]["label"], vec!["bold"]);
+        assert_eq!(exports["flag"]["root"], vec!["red"]);
+
+        let mut library = Organism::new().with_molecules(vec![
+            Molecule::new("button")
+                .with_atom(Atom::new("label").with_imports(vec![("flag", "title")])),
+            Molecule::new("flag")
+                .with_atom(Atom::new("title").with_imports(vec![("button", "label")])),
+        ]);
+        assert!(library
+            .update_exports()
+            .unwrap_err()
+            .starts_with("Circular atom dependency involving"));
+    }
+
+    #[test]
+    fn it_resolves_references_to_other_molecules() {
+        let mut library = Organism::new().with_molecules(vec![
+            Molecule::new("card")
+                .with_atom(Atom::new("root"))
+                .with_css_rule(
+                    CSSRule::new("${root}:hover ${button.label}")
+                        .with_declaration(CSSDeclaration::new("color", "red")),
+                ),
+            Molecule::new("button").with_atom(Atom::new("label")),
+        ]);
+
+        assert_eq!(
+            library.get_css(),
+            ".card_root_41:hover .button_label_0{color:red;}"
+        );
+        let exports = library.get_exports();
+        assert_eq!(exports["button"]["label"], vec!["button_label_0"]);
+
+        let mut library = Organism::new().with_molecule(
+            Molecule::new("card")
+                .with_atom(Atom::new("root"))
+                .with_css_rule(CSSRule::new("${root} ${button.icon}")),
+        );
+        assert_eq!(
+            library.update_exports(),
+            Err("Unknown atom `button.icon` referenced by molecule `card`".to_owned())
+        );
+    }
+
+    #[test]
+    fn it_resolves_references_added_after_insertion() {
+        let mut library = Organism::new().with_molecules(vec![
+            Molecule::new("card").with_atom(Atom::new("root")),
+            Molecule::new("button")
+                .with_atom(Atom::new("label"))
+                .with_css_rule(CSSRule::new("${label}")),
+        ]);
+
+        let card = library.molecules.get_mut("card").unwrap();
+        card.get_nodes_mut().push(CSSNode::CSSRule(
+            CSSRule::new("${root} ${button.label}")
+                .with_declaration(CSSDeclaration::new("color", "red")),
+        ));
+
+        assert_eq!(
+            library.get_css(),
+            ".card_root_0 .button_label_10{color:red;}.button_label_10{}"
+        );
+    }
+
+    #[test]
+    fn it_flattens_nested_rules() {
+        let library = Organism::new().with_molecule(
+            Molecule::new("card")
+                .with_atom(Atom::new("root"))
+                .with_css_rule(
+                    CSSRule::new("${root}")
+                        .with_declaration(CSSDeclaration::new("color", "red"))
+                        .with_rule(
+                            CSSRule::new("&:hover")
+                                .with_declaration(CSSDeclaration::new("color", "blue")),
+                        ),
+                ),
+        );
+
+        assert_eq!(
+            library.get_css(),
+            ".card_root_39{color:red;&:hover{color:blue;}}"
+        );
+        assert_eq!(
+            library.get_flat_css(),
+            ".card_root_39{color:red;}.card_root_39:hover{color:blue;}"
+        );
+    }
+
+    #[test]
+    fn it_serializes_stylesheets() {
+        let mut library = Organism::new()
+            .with_electrons(vec![Electron::new("red", "color", "#ff0000")])
+            .with_molecule(
+                Molecule::new("card")
+                    .with_atom(Atom::new("root").with_electrons(vec!["red"]))
+                    .with_css_rule(
+                        CSSRule::new("${root}")
+                            .with_declaration(CSSDeclaration::new("margin", "0px"))
+                            .with_rule(
+                                CSSRule::new("&:hover")
+                                    .with_declaration(CSSDeclaration::new("opacity", "0.50")),
+                            ),
+                    ),
+            );
+        library.update_exports().unwrap();
+
+        assert_eq!(
+            library.serialize(&PrettySerializer::default(), false),
+            ".red {\n  color: #ff0000;\n}\n\
+             .card_root_42 {\n  margin: 0px;\n  &:hover {\n    opacity: 0.50;\n  }\n}\n"
+        );
+        assert_eq!(
+            library.serialize(&MinifiedSerializer, true),
+            ".red{color:#f00}.card_root_42{margin:0}.card_root_42:hover{opacity:.5}"
+        );
+    }
+
+    #[test]
+    fn it_detects_reference_cycles() {
+        let mut library = Organism::new().with_molecules(vec![
+            Molecule::new("card")
+                .with_atom(Atom::new("root"))
+                .with_css_rule(CSSRule::new("${root} ${button.label}")),
+            Molecule::new("button")
+                .with_atom(Atom::new("label"))
+                .with_css_rule(CSSRule::new("${label} ${card.root}")),
+        ]);
+        assert!(library
+            .update_exports()
+            .unwrap_err()
+            .starts_with("Circular atom dependency involving"));
+    }
+
+    #[test]
+    fn it_applies_overlays() {
+        let mut library = Organism::new()
+            .with_token(Token::new("color.brand", "#ff0000"))
+            .with_electrons(vec![
+                Electron::new("red", "color", "#ff0000"),
+                Electron::new("brand", "border-color", "#ff0000"),
+            ])
+            .with_molecule(
+                Molecule::new("button")
+                    .with_atom(Atom::new("label").with_electrons(vec!["red"]))
+                    .with_css_rule(
+                        CSSRule::new("${label}")
+                            .with_declaration(CSSDeclaration::new("padding", "1rem")),
+                    ),
+            );
+        library.bind_electron("brand", "border-color", "color.brand");
+
+        let overlay = Overlay::new("acme")
+            .with_electron(Electron::new("red", "color", "#ee0000"))
+            .with_token(Token::new("color.brand", "#0000ff"));
+        let acme = library.apply_overlay(&overlay).expect("Failed to apply");
+
+        assert_eq!(
+            acme.get_css(),
+            ":root{--color-brand:#0000ff;}\
+             .red{color:#ee0000}\
+             .brand{border-color:#0000ff}\
+             .button_label_23{padding:1rem;}"
+        );
+        assert_eq!(
+            acme.molecules["button"].get_atom_selector("label"),
+            library.molecules["button"].get_atom_selector("label")
+        );
+
+        let unknown = Overlay::new("acme").with_electron(Electron::new("blue", "color", "#00f"));
+        assert!(library.apply_overlay(&unknown).is_err());
+    }
+
+    #[test]
+    fn it_emits_token_custom_properties() {
+        let library = Organism::new()
+            .with_tokens(vec![
+                Token::new("color.red", "#ff0000"),
+                Token::new("color.brand", "{color.red}"),
+            ])
+            .with_theme(
+                Theme::new("dark")
+                    .with_selector("[data-theme=dark]")
+                    .with_token(Token::new("color.red", "#cc0000")),
+            )
+            .with_electron(Electron::new("brand", "color", "var(--color-brand)"));
+
+        assert_eq!(
+            library.get_css(),
+            ":root{--color-brand:var(--color-red);--color-red:#ff0000;}\
+             [data-theme=dark]{--color-red:#cc0000;}\
+             .brand{color:var(--color-brand)}"
+        );
+    }
 }
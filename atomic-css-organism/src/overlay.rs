@@ -0,0 +1,46 @@
+use derive_more::Display;
+
+use crate::electron::*;
+use crate::token::*;
+
+pub type OverlayName = String;
+
+/// Electron and token values replacing the ones of an organism to produce a
+/// separate stylesheet, e.g. for a white-label brand. Class names are left
+/// untouched so the same markup works with every overlay.
+#[derive(Clone, Eq, PartialEq, Debug, Default, Display)]
+#[display(fmt = "#Overlay({})", name)]
+pub struct Overlay {
+    pub name: OverlayName,
+    pub electrons: Vec<Electron>,
+    pub tokens: Vec<Token>,
+}
+
+impl Overlay {
+    pub fn new(name: &str) -> Self {
+        Self {
+            name: name.to_string(),
+            ..Default::default()
+        }
+    }
+
+    pub fn with_electron(mut self, electron: Electron) -> Self {
+        self.insert_electron(&electron);
+        self
+    }
+
+    pub fn with_token(mut self, token: Token) -> Self {
+        self.insert_token(&token);
+        self
+    }
+
+    pub fn insert_electron(&mut self, electron: &Electron) {
+        self.electrons.retain(|e| e.name != electron.name);
+        self.electrons.push(electron.clone());
+    }
+
+    pub fn insert_token(&mut self, token: &Token) {
+        self.tokens.retain(|t| t.name != token.name);
+        self.tokens.push(token.clone());
+    }
+}
@@ -0,0 +1,284 @@
+use derive_more::Display;
+use serde_json::{Map, Value};
+use std::collections::HashMap;
+
+use crate::atom::*;
+use crate::organism::*;
+
+pub type PropName = String;
+
+/// Name of the atom generated for an atom when the given props are selected,
+/// e.g. `root--size-lg--tone-danger`. It can be referenced in rules like any
+/// other atom, i.e. `${root--size-lg}`.
+pub fn get_prop_atom_name(atom: &str, selection: &[(PropName, String)]) -> AtomName {
+    let mut name = atom.to_string();
+    for (prop, value) in selection {
+        name.push_str(&format!("--{}-{}", prop, value));
+    }
+    name
+}
+
+/// A value of a prop, holding the electrons, imports and extended atoms added
+/// to each of the listed atoms when the value is selected.
+#[derive(Clone, Eq, PartialEq, Debug, Default, Display)]
+#[display(fmt = "#PropValue({})", value)]
+pub struct PropValue {
+    pub value: String,
+    pub atoms: Vec<Atom>,
+}
+
+impl PropValue {
+    pub fn new(value: &str) -> Self {
+        Self {
+            value: value.to_string(),
+            ..Default::default()
+        }
+    }
+
+    pub fn with_atom(mut self, atom: Atom) -> Self {
+        self.insert_atom(&atom);
+        self
+    }
+
+    pub fn insert_atom(&mut self, atom: &Atom) {
+        self.atoms.push(atom.clone());
+    }
+}
+
+/// A component prop, e.g. `size` with the values `sm`, `md` and `lg`.
+#[derive(Clone, Eq, PartialEq, Debug, Default, Display)]
+#[display(fmt = "#Prop({})", name)]
+pub struct Prop {
+    pub name: PropName,
+    pub values: Vec<PropValue>,
+}
+
+impl Prop {
+    pub fn new(name: &str) -> Self {
+        Self {
+            name: name.to_string(),
+            ..Default::default()
+        }
+    }
+
+    pub fn with_value(mut self, value: PropValue) -> Self {
+        self.insert_value(&value);
+        self
+    }
+
+    /// Atoms of a value declared more than once are merged.
+    pub fn insert_value(&mut self, value: &PropValue) {
+        match self.get_value_mut(&value.value) {
+            Some(existing) => existing.atoms.extend(value.atoms.iter().cloned()),
+            None => self.values.push(value.clone()),
+        }
+    }
+
+    pub fn get_value(&self, value: &str) -> Option<&PropValue> {
+        self.values.iter().find(|v| v.value == value)
+    }
+
+    fn get_value_mut(&mut self, value: &str) -> Option<&mut PropValue> {
+        self.values.iter_mut().find(|v| v.value == value)
+    }
+}
+
+/// Electrons applied to atoms only when every listed prop has the given
+/// value, e.g. `size=lg` and `tone=danger`.
+#[derive(Clone, Eq, PartialEq, Debug, Default)]
+pub struct CompoundProp {
+    pub selection: Vec<(PropName, String)>,
+    pub atoms: Vec<Atom>,
+}
+
+impl CompoundProp {
+    pub fn new(selection: Vec<(&str, &str)>) -> Self {
+        Self {
+            selection: selection
+                .into_iter()
+                .map(|(prop, value)| (prop.to_string(), value.to_string()))
+                .collect(),
+            ..Default::default()
+        }
+    }
+
+    pub fn with_atom(mut self, atom: Atom) -> Self {
+        self.insert_atom(&atom);
+        self
+    }
+
+    pub fn insert_atom(&mut self, atom: &Atom) {
+        self.atoms.push(atom.clone());
+    }
+
+    pub fn matches(&self, selection: &[(PropName, String)]) -> bool {
+        self.selection
+            .iter()
+            .all(|condition| selection.contains(condition))
+    }
+}
+
+/// Classes to apply to each atom of a molecule for a selection of props.
+pub type PropTable = Vec<(Vec<(PropName, String)>, HashMap<AtomName, Vec<String>>)>;
+
+impl Organism {
+    /// Lookup table of the classes of every atom for each combination of prop
+    /// values: the atom classes, then the ones of the selected values in prop
+    /// order, then the ones of the matching compounds. Exports must be up to
+    /// date.
+    pub fn get_prop_table(&self, molecule_name: &str) -> PropTable {
+        let mut table = vec![];
+        let Some(molecule) = self.molecules.get(molecule_name) else {
+            return table;
+        };
+
+        for selection in molecule.get_prop_selections() {
+            let mut atoms = HashMap::new();
+            for atom_name in molecule.get_base_atoms() {
+                let mut prop_atoms = vec![atom_name.to_owned()];
+                for prop in &selection {
+                    prop_atoms.push(get_prop_atom_name(atom_name, &[prop.to_owned()]));
+                }
+                for compound in &molecule.compounds {
+                    if compound.matches(&selection) {
+                        prop_atoms.push(get_prop_atom_name(atom_name, &compound.selection));
+                    }
+                }
+
+                let classes: Vec<String> = prop_atoms
+                    .iter()
+                    .filter_map(|name| self.get_exported_classes(molecule_name, name))
+                    .flatten()
+                    .cloned()
+                    .collect();
+                atoms.insert(
+                    atom_name.to_owned(),
+                    self.resolve_electron_conflicts(&classes),
+                );
+            }
+            table.push((selection, atoms));
+        }
+
+        table
+    }
+
+    /// Exports the prop tables of every molecule with props as JSON, keyed by
+    /// molecule, then by selection, e.g. `size=lg,tone=danger`, then by atom.
+    pub fn export_prop_tables(&self) -> Result<String, String> {
+        let mut root = Map::new();
+        for (molecule_name, molecule) in &self.molecules {
+            if molecule.props.is_empty() {
+                continue;
+            }
+            let mut selections = Map::new();
+            for (selection, atoms) in self.get_prop_table(molecule_name) {
+                let key = selection
+                    .iter()
+                    .map(|(prop, value)| format!("{}={}", prop, value))
+                    .collect::<Vec<String>>()
+                    .join(",");
+                let atoms = atoms
+                    .into_iter()
+                    .map(|(atom, classes)| (atom, Value::String(classes.join(" "))))
+                    .collect();
+                selections.insert(key, Value::Object(atoms));
+            }
+            root.insert(molecule_name.to_owned(), Value::Object(selections));
+        }
+
+        serde_json::to_string_pretty(&Value::Object(root))
+            .map_err(|error| format!("Failed to serialize props: {}", error))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::electron::*;
+    use crate::molecule::*;
+
+    #[test]
+    fn it_builds_prop_tables() {
+        let mut organism = Organism::new()
+            .with_electrons(vec![
+                Electron::new("blue", "color", "#0000ff"),
+                Electron::new("red", "color", "#ff0000"),
+                Electron::new("small", "font-size", "12px"),
+                Electron::new("large", "font-size", "20px"),
+                Electron::new("bold", "font-weight", "bold"),
+            ])
+            .with_molecule(
+                Molecule::new("button")
+                    .with_atom(Atom::new("root").with_electrons(vec!["blue", "small"]))
+                    .with_prop(
+                        Prop::new("size")
+                            .with_value(PropValue::new("sm"))
+                            .with_value(
+                                PropValue::new("lg")
+                                    .with_atom(Atom::new("root").with_electrons(vec!["large"])),
+                            ),
+                    )
+                    .with_prop(
+                        Prop::new("tone")
+                            .with_value(PropValue::new("primary"))
+                            .with_value(
+                                PropValue::new("danger")
+                                    .with_atom(Atom::new("root").with_electrons(vec!["red"])),
+                            ),
+                    )
+                    .with_compound(
+                        CompoundProp::new(vec![("size", "lg"), ("tone", "danger")])
+                            .with_atom(Atom::new("root").with_electrons(vec!["bold"])),
+                    ),
+            );
+        organism.update_exports().unwrap();
+
+        let table: Vec<(String, Vec<String>)> = organism
+            .get_prop_table("button")
+            .into_iter()
+            .map(|(selection, atoms)| {
+                let key = selection
+                    .iter()
+                    .map(|(prop, value)| format!("{}={}", prop, value))
+                    .collect::<Vec<String>>()
+                    .join(",");
+                (key, atoms["root"].clone())
+            })
+            .collect();
+        assert_eq!(
+            table,
+            vec![
+                (
+                    "size=sm,tone=primary".to_owned(),
+                    vec!["blue".to_owned(), "small".to_owned()]
+                ),
+                (
+                    "size=sm,tone=danger".to_owned(),
+                    vec!["small".to_owned(), "red".to_owned()]
+                ),
+                (
+                    "size=lg,tone=primary".to_owned(),
+                    vec!["blue".to_owned(), "large".to_owned()]
+                ),
+                (
+                    "size=lg,tone=danger".to_owned(),
+                    vec!["large".to_owned(), "red".to_owned(), "bold".to_owned()]
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn it_names_prop_atoms() {
+        let selection = vec![
+            ("size".to_string(), "lg".to_string()),
+            ("tone".to_string(), "danger".to_string()),
+        ];
+        assert_eq!(
+            get_prop_atom_name("root", &selection),
+            "root--size-lg--tone-danger"
+        );
+        assert!(CompoundProp::new(vec![("tone", "danger")]).matches(&selection));
+        assert!(!CompoundProp::new(vec![("tone", "primary")]).matches(&selection));
+    }
+}
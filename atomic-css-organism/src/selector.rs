@@ -0,0 +1,807 @@
+use derive_more::Display;
+use std::collections::HashMap;
+use std::fmt;
+use std::iter::Peekable;
+use std::ops::Add;
+use std::str::{Chars, FromStr};
+
+use crate::atom::*;
+use crate::molecule::*;
+
+/// Specificity of a selector as `(ids, classes, types)`, compared in order.
+#[derive(Clone, Copy, Eq, PartialEq, Ord, PartialOrd, Hash, Debug, Default, Display)]
+#[display(fmt = "({},{},{})", ids, classes, types)]
+pub struct Specificity {
+    pub ids: usize,
+    pub classes: usize,
+    pub types: usize,
+}
+
+impl Specificity {
+    pub fn new(ids: usize, classes: usize, types: usize) -> Self {
+        Self {
+            ids,
+            classes,
+            types,
+        }
+    }
+}
+
+impl FromStr for Specificity {
+    type Err = String;
+
+    /// Parses `ids,classes,types`, e.g. `0,3,0`.
+    fn from_str(specificity: &str) -> Result<Self, Self::Err> {
+        let parts: Vec<usize> = specificity
+            .split(',')
+            .map(|part| part.trim().parse::<usize>())
+            .collect::<Result<_, _>>()
+            .map_err(|_| format!("Invalid specificity `{}`", specificity))?;
+        match parts[..] {
+            [ids, classes, types] => Ok(Specificity::new(ids, classes, types)),
+            _ => Err(format!("Invalid specificity `{}`", specificity)),
+        }
+    }
+}
+
+impl Add for Specificity {
+    type Output = Specificity;
+
+    fn add(self, other: Specificity) -> Specificity {
+        Specificity::new(
+            self.ids + other.ids,
+            self.classes + other.classes,
+            self.types + other.types,
+        )
+    }
+}
+
+#[derive(Clone, Copy, Eq, PartialEq, Debug, Display)]
+pub enum Combinator {
+    #[display(fmt = " ")]
+    Descendant,
+    #[display(fmt = ">")]
+    Child,
+    #[display(fmt = "+")]
+    NextSibling,
+    #[display(fmt = "~")]
+    SubsequentSibling,
+}
+
+#[derive(Clone, Eq, PartialEq, Debug)]
+pub enum SimpleSelector {
+    /// `&`, the parent selector of a nested rule.
+    Nesting,
+    Universal,
+    Type(String),
+    Class(String),
+    Id(String),
+    /// `[name]` or `[name<matcher>value]`, the value kept as written along
+    /// with its quotes and flags.
+    Attribute {
+        name: String,
+        matcher: Option<String>,
+        value: Option<String>,
+    },
+    PseudoClass {
+        name: String,
+        argument: Option<String>,
+    },
+    PseudoElement {
+        name: String,
+        argument: Option<String>,
+    },
+    /// `${atom}` or `${molecule.atom}`, resolved to the class of the atom.
+    AtomReference {
+        molecule: Option<MoleculeName>,
+        atom: AtomName,
+    },
+}
+
+impl fmt::Display for SimpleSelector {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SimpleSelector::Nesting => write!(f, "&"),
+            SimpleSelector::Universal => write!(f, "*"),
+            SimpleSelector::Type(name) => write!(f, "{}", name),
+            SimpleSelector::Class(name) => write!(f, ".{}", name),
+            SimpleSelector::Id(name) => write!(f, "#{}", name),
+            SimpleSelector::Attribute {
+                name,
+                matcher,
+                value,
+            } => match (matcher, value) {
+                (Some(matcher), Some(value)) => write!(f, "[{}{}{}]", name, matcher, value),
+                _ => write!(f, "[{}]", name),
+            },
+            SimpleSelector::PseudoClass { name, argument } => match argument {
+                Some(argument) => write!(f, ":{}({})", name, argument),
+                None => write!(f, ":{}", name),
+            },
+            SimpleSelector::PseudoElement { name, argument } => match argument {
+                Some(argument) => write!(f, "::{}({})", name, argument),
+                None => write!(f, "::{}", name),
+            },
+            SimpleSelector::AtomReference { molecule, atom } => match molecule {
+                Some(molecule) => write!(f, "${{{}.{}}}", molecule, atom),
+                None => write!(f, "${{{}}}", atom),
+            },
+        }
+    }
+}
+
+impl SimpleSelector {
+    /// Arguments of the pseudo-classes taking a selector list, e.g. `:not()`.
+    pub fn get_selector_argument(&self) -> Option<Result<SelectorList, String>> {
+        match self {
+            SimpleSelector::PseudoClass {
+                name,
+                argument: Some(argument),
+            } if matches!(name.as_str(), "not" | "is" | "where" | "has") => {
+                Some(SelectorList::parse(argument))
+            }
+            _ => None,
+        }
+    }
+
+    /// Specificity, `&` taking the one of `parent`.
+    pub fn get_specificity(&self, parent: Specificity) -> Specificity {
+        match self {
+            SimpleSelector::Nesting => parent,
+            SimpleSelector::Universal => Specificity::default(),
+            SimpleSelector::Type(_) | SimpleSelector::PseudoElement { .. } => {
+                Specificity::new(0, 0, 1)
+            }
+            SimpleSelector::Id(_) => Specificity::new(1, 0, 0),
+            SimpleSelector::PseudoClass { name, .. } if name == "where" => Specificity::default(),
+            SimpleSelector::PseudoClass { .. } => match self.get_selector_argument() {
+                Some(Ok(selectors)) => selectors.get_specificity(parent),
+                _ => Specificity::new(0, 1, 0),
+            },
+            SimpleSelector::Class(_)
+            | SimpleSelector::Attribute { .. }
+            | SimpleSelector::AtomReference { .. } => Specificity::new(0, 1, 0),
+        }
+    }
+}
+
+/// Simple selectors applying to the same element, e.g. `a.link:hover`.
+#[derive(Clone, Eq, PartialEq, Debug, Default)]
+pub struct CompoundSelector(pub Vec<SimpleSelector>);
+
+impl fmt::Display for CompoundSelector {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for selector in &self.0 {
+            write!(f, "{}", selector)?;
+        }
+        Ok(())
+    }
+}
+
+impl CompoundSelector {
+    pub fn has_nesting(&self) -> bool {
+        self.0.iter().any(|selector| match selector {
+            SimpleSelector::Nesting => true,
+            _ => matches!(
+                selector.get_selector_argument(),
+                Some(Ok(argument)) if argument.has_nesting()
+            ),
+        })
+    }
+
+    /// Replaces `&` with `parent`, whose ancestors are kept in front since
+    /// `&` may stand for a chain of compounds. `parents` resolves the `&` of
+    /// arguments, e.g. `:not(&)`.
+    fn resolve_nesting(&self, parent: &ComplexSelector, parents: &SelectorList) -> ComplexSelector {
+        let mut ancestors = None;
+        let mut selectors = vec![];
+        for selector in &self.0 {
+            match selector {
+                SimpleSelector::Nesting => {
+                    let (last, ancestor) = parent.split_last();
+                    selectors.extend(last.0.iter().cloned());
+                    ancestors = ancestor;
+                }
+                SimpleSelector::PseudoClass {
+                    name,
+                    argument: Some(_),
+                } => match selector.get_selector_argument() {
+                    Some(Ok(argument)) if argument.has_nesting() => {
+                        selectors.push(SimpleSelector::PseudoClass {
+                            name: name.to_owned(),
+                            argument: Some(argument.resolve_nesting(parents).to_string()),
+                        })
+                    }
+                    _ => selectors.push(selector.to_owned()),
+                },
+                _ => selectors.push(selector.to_owned()),
+            }
+        }
+        // type selectors must come first, e.g. `a&` with `.link` is `a.link`
+        selectors.sort_by_key(|selector| {
+            !matches!(
+                selector,
+                SimpleSelector::Type(_) | SimpleSelector::Universal
+            )
+        });
+
+        match ancestors {
+            Some((mut ancestors, combinator)) => {
+                ancestors
+                    .rest
+                    .push((combinator, CompoundSelector(selectors)));
+                ancestors
+            }
+            None => ComplexSelector {
+                first: CompoundSelector(selectors),
+                rest: vec![],
+            },
+        }
+    }
+
+    pub fn get_specificity(&self, parent: Specificity) -> Specificity {
+        self.0
+            .iter()
+            .fold(Specificity::default(), |specificity, selector| {
+                specificity + selector.get_specificity(parent)
+            })
+    }
+}
+
+/// Compound selectors joined by combinators, e.g. `.card:hover > a`.
+#[derive(Clone, Eq, PartialEq, Debug, Default)]
+pub struct ComplexSelector {
+    pub first: CompoundSelector,
+    pub rest: Vec<(Combinator, CompoundSelector)>,
+}
+
+impl fmt::Display for ComplexSelector {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.first)?;
+        for (combinator, compound) in &self.rest {
+            match combinator {
+                Combinator::Descendant => write!(f, " {}", compound)?,
+                _ => write!(f, " {} {}", combinator, compound)?,
+            }
+        }
+        Ok(())
+    }
+}
+
+impl ComplexSelector {
+    pub fn has_nesting(&self) -> bool {
+        self.get_compounds().any(|compound| compound.has_nesting())
+    }
+
+    /// The last compound, along with the selector leading to it and the
+    /// combinator joining them, if any.
+    fn split_last(&self) -> (&CompoundSelector, Option<(ComplexSelector, Combinator)>) {
+        match self.rest.split_last() {
+            Some(((combinator, last), rest)) => {
+                let ancestors = ComplexSelector {
+                    first: self.first.clone(),
+                    rest: rest.to_vec(),
+                };
+                (last, Some((ancestors, *combinator)))
+            }
+            None => (&self.first, None),
+        }
+    }
+
+    /// Resolves `&` against `parent`, which is prepended as an ancestor when
+    /// the selector has no `&`, as in nested rules.
+    fn resolve_nesting(&self, parent: &ComplexSelector, parents: &SelectorList) -> ComplexSelector {
+        if !self.has_nesting() {
+            let mut resolved = parent.clone();
+            resolved
+                .rest
+                .push((Combinator::Descendant, self.first.clone()));
+            resolved.rest.extend(self.rest.iter().cloned());
+            return resolved;
+        }
+
+        let mut resolved = self.first.resolve_nesting(parent, parents);
+        for (combinator, compound) in &self.rest {
+            let compound = compound.resolve_nesting(parent, parents);
+            resolved.rest.push((*combinator, compound.first));
+            resolved.rest.extend(compound.rest);
+        }
+        resolved
+    }
+
+    pub fn get_compounds(&self) -> impl Iterator<Item = &CompoundSelector> {
+        std::iter::once(&self.first).chain(self.rest.iter().map(|(_, compound)| compound))
+    }
+
+    pub fn get_specificity(&self, parent: Specificity) -> Specificity {
+        self.get_compounds()
+            .fold(Specificity::default(), |specificity, compound| {
+                specificity + compound.get_specificity(parent)
+            })
+    }
+
+    /// Number of descendant combinators, i.e. the depth of the chain.
+    pub fn get_descendant_depth(&self) -> usize {
+        self.rest
+            .iter()
+            .filter(|(combinator, _)| *combinator == Combinator::Descendant)
+            .count()
+    }
+}
+
+/// A comma separated list of selectors, as found in a rule.
+#[derive(Clone, Eq, PartialEq, Debug, Default)]
+pub struct SelectorList(pub Vec<ComplexSelector>);
+
+impl fmt::Display for SelectorList {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let selectors: Vec<String> = self.0.iter().map(|s| s.to_string()).collect();
+        write!(f, "{}", selectors.join(", "))
+    }
+}
+
+impl SelectorList {
+    pub fn has_nesting(&self) -> bool {
+        self.0.iter().any(|selector| selector.has_nesting())
+    }
+
+    /// Selectors of a rule nested in a rule with the `parent` selectors, e.g.
+    /// `&:hover, a` in `.x, .y` is `.x:hover, .x a, .y:hover, .y a`.
+    pub fn resolve_nesting(&self, parent: &SelectorList) -> SelectorList {
+        let mut resolved = vec![];
+        for parent_selector in &parent.0 {
+            for selector in &self.0 {
+                resolved.push(selector.resolve_nesting(parent_selector, parent));
+            }
+        }
+        SelectorList(resolved)
+    }
+
+    pub fn parse(selector: &str) -> Result<Self, String> {
+        SelectorParser::new(selector)
+            .parse_list()
+            .map_err(|error| format!("Invalid selector `{}`: {}", selector, error))
+    }
+
+    /// Specificity of the most specific selector of the list, `&` taking the
+    /// one of `parent`.
+    pub fn get_specificity(&self, parent: Specificity) -> Specificity {
+        self.0
+            .iter()
+            .map(|selector| selector.get_specificity(parent))
+            .max()
+            .unwrap_or_default()
+    }
+
+    /// Atoms referenced by the selectors, including the ones within `:not()`
+    /// and alike.
+    pub fn get_atom_references(&self) -> Vec<(Option<MoleculeName>, AtomName)> {
+        let mut references = vec![];
+        for compound in self.0.iter().flat_map(|s| s.get_compounds()) {
+            for selector in &compound.0 {
+                match selector {
+                    SimpleSelector::AtomReference { molecule, atom } => {
+                        references.push((molecule.to_owned(), atom.to_owned()))
+                    }
+                    _ => {
+                        if let Some(Ok(selectors)) = selector.get_selector_argument() {
+                            references.extend(selectors.get_atom_references());
+                        }
+                    }
+                }
+            }
+        }
+        references
+    }
+
+    /// Replaces the atom references with the selectors of the atoms, keyed
+    /// as in the placeholders, i.e. `atom` or `molecule.atom`.
+    pub fn resolve_atom_references(
+        &self,
+        selectors: &HashMap<String, String>,
+    ) -> Result<SelectorList, String> {
+        let mut resolved = self.clone();
+        for complex in resolved.0.iter_mut() {
+            let compounds = std::iter::once(&mut complex.first)
+                .chain(complex.rest.iter_mut().map(|(_, compound)| compound));
+            for compound in compounds {
+                let mut simple_selectors = vec![];
+                for selector in &compound.0 {
+                    match selector {
+                        SimpleSelector::AtomReference { molecule, atom } => {
+                            let key = match molecule {
+                                Some(molecule) => format!("{}.{}", molecule, atom),
+                                None => atom.to_owned(),
+                            };
+                            let Some(atom_selector) = selectors.get(&key) else {
+                                return Err(format!("Unknown atom `{}`", key));
+                            };
+                            let atom_selector = SelectorList::parse(atom_selector)?;
+                            match &atom_selector.0[..] {
+                                [ComplexSelector { first, rest }] if rest.is_empty() => {
+                                    simple_selectors.extend(first.0.iter().cloned())
+                                }
+                                _ => {
+                                    return Err(format!(
+                                        "Atom `{}` does not resolve to a compound selector",
+                                        key
+                                    ))
+                                }
+                            }
+                        }
+                        SimpleSelector::PseudoClass {
+                            name,
+                            argument: Some(_),
+                        } => match selector.get_selector_argument() {
+                            Some(Ok(argument)) => {
+                                simple_selectors.push(SimpleSelector::PseudoClass {
+                                    name: name.to_owned(),
+                                    argument: Some(
+                                        argument.resolve_atom_references(selectors)?.to_string(),
+                                    ),
+                                })
+                            }
+                            _ => simple_selectors.push(selector.to_owned()),
+                        },
+                        _ => simple_selectors.push(selector.to_owned()),
+                    }
+                }
+                compound.0 = simple_selectors;
+            }
+        }
+        Ok(resolved)
+    }
+}
+
+/// Maximum nesting of brackets within an argument, e.g. `:not(:is(...))`.
+const MAX_NESTING: usize = 32;
+
+fn is_name_char(c: char) -> bool {
+    c.is_ascii_alphanumeric() || c == '-' || c == '_' || !c.is_ascii()
+}
+
+struct SelectorParser<'a> {
+    chars: Peekable<Chars<'a>>,
+}
+
+impl<'a> SelectorParser<'a> {
+    fn new(selector: &'a str) -> Self {
+        Self {
+            chars: selector.chars().peekable(),
+        }
+    }
+
+    fn skip_whitespace(&mut self) -> bool {
+        let mut skipped = false;
+        while self.chars.next_if(|c| c.is_whitespace()).is_some() {
+            skipped = true;
+        }
+        skipped
+    }
+
+    /// A name, possibly with escaped characters, e.g. `hover\:red`.
+    fn parse_name(&mut self) -> Result<String, String> {
+        let mut name = String::new();
+        while let Some(&c) = self.chars.peek() {
+            if c == '\\' {
+                self.chars.next();
+                let Some(escaped) = self.chars.next() else {
+                    return Err("unterminated escape".to_owned());
+                };
+                name.push('\\');
+                name.push(escaped);
+            } else if is_name_char(c) {
+                name.push(c);
+                self.chars.next();
+            } else {
+                break;
+            }
+        }
+        if name.is_empty() {
+            return Err("expected a name".to_owned());
+        }
+        Ok(name)
+    }
+
+    /// Everything up to the matching `close`, which is consumed.
+    fn parse_until(&mut self, close: char) -> Result<String, String> {
+        let open = match close {
+            ')' => '(',
+            ']' => '[',
+            _ => '{',
+        };
+        let mut depth = 0;
+        let mut quote = None;
+        let mut content = String::new();
+        while let Some(c) = self.chars.next() {
+            match (quote, c) {
+                (Some(q), c) if c == q => quote = None,
+                (Some(_), '\\') => {
+                    content.push(c);
+                    if let Some(escaped) = self.chars.next() {
+                        content.push(escaped);
+                    }
+                    continue;
+                }
+                (Some(_), _) => {}
+                (None, '"' | '\'') => quote = Some(c),
+                (None, c) if c == open => {
+                    depth += 1;
+                    if depth > MAX_NESTING {
+                        return Err("nested too deeply".to_owned());
+                    }
+                }
+                (None, c) if c == close && depth == 0 => return Ok(content),
+                (None, c) if c == close => depth -= 1,
+                _ => {}
+            }
+            content.push(c);
+        }
+        Err(format!("expected `{}`", close))
+    }
+
+    fn parse_argument(&mut self) -> Result<Option<String>, String> {
+        if self.chars.next_if_eq(&'(').is_none() {
+            return Ok(None);
+        }
+        Ok(Some(self.parse_until(')')?.trim().to_owned()))
+    }
+
+    fn parse_attribute(&mut self) -> Result<SimpleSelector, String> {
+        let content = self.parse_until(']')?;
+        let content = content.trim();
+        let matcher_start = content.find(['=', '~', '|', '^', '$', '*']);
+        let Some(start) = matcher_start else {
+            let mut parser = SelectorParser::new(content);
+            let name = parser.parse_name()?;
+            if parser.chars.next().is_some() {
+                return Err(format!("invalid attribute `{}`", content));
+            }
+            return Ok(SimpleSelector::Attribute {
+                name,
+                matcher: None,
+                value: None,
+            });
+        };
+        let name = content[..start].trim();
+        let rest = &content[start..];
+        let matcher_len = if rest.starts_with('=') { 1 } else { 2 };
+        if name.is_empty() || !rest.is_char_boundary(matcher_len) {
+            return Err(format!("invalid attribute `{}`", content));
+        }
+        let matcher = &rest[..matcher_len];
+        let value = rest[matcher_len..].trim();
+        if !matcher.ends_with('=') || value.is_empty() {
+            return Err(format!("invalid attribute `{}`", content));
+        }
+        Ok(SimpleSelector::Attribute {
+            name: name.to_owned(),
+            matcher: Some(matcher.to_owned()),
+            value: Some(value.to_owned()),
+        })
+    }
+
+    fn parse_atom_reference(&mut self) -> Result<SimpleSelector, String> {
+        self.chars.next();
+        if self.chars.next_if_eq(&'{').is_none() {
+            return Err("expected `{` after `$`".to_owned());
+        }
+        let placeholder = self.parse_until('}')?;
+        if placeholder.is_empty() {
+            return Err("empty atom reference".to_owned());
+        }
+        Ok(match parse_atom_reference(&placeholder) {
+            Some((molecule, atom)) => SimpleSelector::AtomReference {
+                molecule: Some(molecule.to_owned()),
+                atom: atom.to_owned(),
+            },
+            None => SimpleSelector::AtomReference {
+                molecule: None,
+                atom: placeholder,
+            },
+        })
+    }
+
+    fn parse_simple(&mut self) -> Result<Option<SimpleSelector>, String> {
+        let Some(&c) = self.chars.peek() else {
+            return Ok(None);
+        };
+        let selector = match c {
+            '&' => {
+                self.chars.next();
+                SimpleSelector::Nesting
+            }
+            '*' => {
+                self.chars.next();
+                SimpleSelector::Universal
+            }
+            '.' => {
+                self.chars.next();
+                SimpleSelector::Class(self.parse_name()?)
+            }
+            '#' => {
+                self.chars.next();
+                SimpleSelector::Id(self.parse_name()?)
+            }
+            '[' => {
+                self.chars.next();
+                self.parse_attribute()?
+            }
+            '$' => self.parse_atom_reference()?,
+            ':' => {
+                self.chars.next();
+                if self.chars.next_if_eq(&':').is_some() {
+                    let name = self.parse_name()?;
+                    let argument = self.parse_argument()?;
+                    SimpleSelector::PseudoElement { name, argument }
+                } else {
+                    let name = self.parse_name()?;
+                    let argument = self.parse_argument()?;
+                    SimpleSelector::PseudoClass { name, argument }
+                }
+            }
+            c if is_name_char(c) || c == '\\' => SimpleSelector::Type(self.parse_name()?),
+            _ => return Ok(None),
+        };
+        Ok(Some(selector))
+    }
+
+    fn parse_compound(&mut self) -> Result<CompoundSelector, String> {
+        let mut compound = CompoundSelector::default();
+        while let Some(selector) = self.parse_simple()? {
+            compound.0.push(selector);
+        }
+        if compound.0.is_empty() {
+            return match self.chars.peek() {
+                Some(c) => Err(format!("unexpected `{}`", c)),
+                None => Err("expected a selector".to_owned()),
+            };
+        }
+        Ok(compound)
+    }
+
+    fn parse_complex(&mut self) -> Result<ComplexSelector, String> {
+        self.skip_whitespace();
+        let mut complex = ComplexSelector {
+            first: self.parse_compound()?,
+            rest: vec![],
+        };
+        loop {
+            let whitespace = self.skip_whitespace();
+            let combinator = match self.chars.peek() {
+                None | Some(',') => break,
+                Some('>') => Combinator::Child,
+                Some('+') => Combinator::NextSibling,
+                Some('~') => Combinator::SubsequentSibling,
+                Some(_) if whitespace => Combinator::Descendant,
+                Some(c) => return Err(format!("unexpected `{}`", c)),
+            };
+            if combinator != Combinator::Descendant {
+                self.chars.next();
+                self.skip_whitespace();
+            }
+            complex.rest.push((combinator, self.parse_compound()?));
+        }
+        Ok(complex)
+    }
+
+    fn parse_list(&mut self) -> Result<SelectorList, String> {
+        let mut list = SelectorList(vec![self.parse_complex()?]);
+        while self.chars.next_if_eq(&',').is_some() {
+            list.0.push(self.parse_complex()?);
+        }
+        Ok(list)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_parses_selectors() {
+        let selectors = SelectorList::parse("${root}:hover > a.link, &:not(.a, #b)").unwrap();
+        assert_eq!(
+            selectors.0[0],
+            ComplexSelector {
+                first: CompoundSelector(vec![
+                    SimpleSelector::AtomReference {
+                        molecule: None,
+                        atom: "root".to_owned()
+                    },
+                    SimpleSelector::PseudoClass {
+                        name: "hover".to_owned(),
+                        argument: None
+                    },
+                ]),
+                rest: vec![(
+                    Combinator::Child,
+                    CompoundSelector(vec![
+                        SimpleSelector::Type("a".to_owned()),
+                        SimpleSelector::Class("link".to_owned()),
+                    ])
+                )],
+            }
+        );
+        assert_eq!(
+            selectors.to_string(),
+            "${root}:hover > a.link, &:not(.a, #b)"
+        );
+        assert_eq!(
+            SelectorList::parse("[data-theme = \"dark\" i] ${button.label}::before")
+                .unwrap()
+                .to_string(),
+            "[data-theme=\"dark\" i] ${button.label}::before"
+        );
+        assert_eq!(
+            SelectorList::parse(".hover\\:red:hover")
+                .unwrap()
+                .to_string(),
+            ".hover\\:red:hover"
+        );
+    }
+
+    #[test]
+    fn it_rejects_invalid_selectors() {
+        for selector in [
+            "", "a >", "a,", ".", "a[b", ":not(a", "${root", "a ! b", "[=b]",
+        ] {
+            assert!(SelectorList::parse(selector).is_err(), "{}", selector);
+        }
+    }
+
+    #[test]
+    fn it_computes_specificity() {
+        let specificity = |selector| {
+            SelectorList::parse(selector)
+                .unwrap()
+                .get_specificity(Specificity::new(0, 1, 0))
+        };
+        assert_eq!(specificity("#a .b c"), Specificity::new(1, 1, 1));
+        assert_eq!(
+            specificity("${root}:hover::before"),
+            Specificity::new(0, 2, 1)
+        );
+        assert_eq!(specificity("&:not(#a, .b) [c]"), Specificity::new(1, 2, 0));
+        assert_eq!(specificity(":where(#a) *"), Specificity::new(0, 0, 0));
+        assert!(Specificity::new(1, 0, 0) > Specificity::new(0, 9, 9));
+    }
+
+    #[test]
+    fn it_resolves_atom_references() {
+        let selectors = HashMap::from([
+            ("root".to_string(), ".flag_root_12".to_string()),
+            ("button.label".to_string(), ".button_label_0".to_string()),
+        ]);
+        assert_eq!(
+            SelectorList::parse("${root}:not(${button.label}) > ${button.label}")
+                .unwrap()
+                .resolve_atom_references(&selectors)
+                .unwrap()
+                .to_string(),
+            ".flag_root_12:not(.button_label_0) > .button_label_0"
+        );
+        assert!(SelectorList::parse("${rot}")
+            .unwrap()
+            .resolve_atom_references(&selectors)
+            .is_err());
+    }
+
+    #[test]
+    fn it_resolves_nesting() {
+        let resolve = |parent: &str, selector: &str| {
+            SelectorList::parse(selector)
+                .unwrap()
+                .resolve_nesting(&SelectorList::parse(parent).unwrap())
+                .to_string()
+        };
+        assert_eq!(resolve("${root}", "&:hover"), "${root}:hover");
+        assert_eq!(
+            resolve(".a, .b", "&:hover, p"),
+            ".a:hover, .a p, .b:hover, .b p"
+        );
+        assert_eq!(resolve(".a > .b", ".x &"), ".x .a > .b");
+        assert_eq!(resolve(".a .b", "a& + &"), ".a a.b + .a .b");
+        assert_eq!(resolve(".a", ":not(&) span"), ":not(.a) span");
+    }
+}
@@ -0,0 +1,566 @@
+use std::str::FromStr;
+
+use crate::css::*;
+
+/// A stylesheet being written, along with the spans of the nodes it is
+/// written from, e.g. for source maps.
+#[derive(Clone, Eq, PartialEq, Debug, Default)]
+pub struct Output {
+    pub css: String,
+    /// Offsets in `css` at which the node with the span starts.
+    pub mappings: Vec<(usize, Span)>,
+}
+
+impl Output {
+    pub fn push(&mut self, c: char) {
+        self.css.push(c);
+    }
+
+    pub fn push_str(&mut self, css: &str) {
+        self.css.push_str(css);
+    }
+
+    pub fn len(&self) -> usize {
+        self.css.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.css.is_empty()
+    }
+
+    pub fn add_mapping(&mut self, span: &Span) {
+        self.mappings.push((self.css.len(), span.to_owned()));
+    }
+
+    /// Drops the css written from `len` on, mappings included.
+    pub fn truncate(&mut self, len: usize) {
+        self.css.truncate(len);
+        self.mappings.retain(|(offset, _)| *offset < len);
+    }
+}
+
+/// Turns a tree of css nodes into a stylesheet, `depth` being the nesting
+/// level of the node.
+pub trait Serializer {
+    fn write_declaration(&self, declaration: &CSSDeclaration, depth: usize, output: &mut Output);
+
+    fn write_rule(&self, rule: &CSSRule, depth: usize, output: &mut Output);
+
+    fn write_at_rule(&self, at_rule: &CSSAtRule, depth: usize, output: &mut Output);
+
+    /// Written before every node, e.g. the indentation.
+    fn write_indent(&self, _depth: usize, _output: &mut Output) {}
+
+    fn write_node(&self, node: &CSSNode, depth: usize, output: &mut Output) {
+        self.write_indent(depth, output);
+        if let Some(span) = node.get_span() {
+            output.add_mapping(span);
+        }
+        match node {
+            CSSNode::CSSDeclaration(declaration) => {
+                self.write_declaration(declaration, depth, output)
+            }
+            CSSNode::CSSRule(rule) => self.write_rule(rule, depth, output),
+            CSSNode::CSSAtRule(at_rule) => self.write_at_rule(at_rule, depth, output),
+        }
+    }
+
+    fn write_nodes(&self, nodes: &[CSSNode], depth: usize, output: &mut Output) {
+        for node in nodes {
+            self.write_node(node, depth, output);
+        }
+    }
+
+    /// Writes the top level rule of an electron or of an electron variant,
+    /// same as any rule by default.
+    fn write_electron_rule(&self, rule: &CSSRule, output: &mut Output) {
+        self.write_node(&CSSNode::CSSRule(rule.to_owned()), 0, output);
+    }
+
+    fn serialize_node(&self, node: &CSSNode, depth: usize) -> String {
+        let mut output = Output::default();
+        self.write_node(node, depth, &mut output);
+        output.css
+    }
+
+    fn serialize_nodes(&self, nodes: &[CSSNode], depth: usize) -> String {
+        let mut output = Output::default();
+        self.write_nodes(nodes, depth, &mut output);
+        output.css
+    }
+}
+
+/// The default output, without whitespace, e.g. `.a{color:red;}`.
+#[derive(Clone, Copy, Eq, PartialEq, Debug, Default)]
+pub struct CompactSerializer;
+
+impl Serializer for CompactSerializer {
+    fn write_declaration(&self, declaration: &CSSDeclaration, _depth: usize, output: &mut Output) {
+        output.push_str(&format!("{}:{};", declaration.property, declaration.value));
+    }
+
+    fn write_rule(&self, rule: &CSSRule, depth: usize, output: &mut Output) {
+        output.push_str(&rule.selector);
+        output.push('{');
+        self.write_nodes(rule.get_children(), depth + 1, output);
+        output.push('}');
+    }
+
+    fn write_at_rule(&self, at_rule: &CSSAtRule, depth: usize, output: &mut Output) {
+        output.push_str(&format!("@{}", at_rule.name));
+        if let Some(params) = &at_rule.params {
+            output.push_str(&format!(" {}", params));
+        }
+        if at_rule.get_children().is_empty() {
+            output.push(';');
+            return;
+        }
+        output.push('{');
+        self.write_nodes(at_rule.get_children(), depth + 1, output);
+        output.push('}');
+    }
+
+    /// Electron rules have no trailing semicolon, e.g. `.red{color:red}`.
+    fn write_electron_rule(&self, rule: &CSSRule, output: &mut Output) {
+        if let Some(span) = &rule.span {
+            output.add_mapping(span);
+        }
+        output.push_str(&rule.selector);
+        output.push('{');
+        for (i, child) in rule.get_children().iter().enumerate() {
+            if i > 0 {
+                output.push(';');
+            }
+            if let CSSNode::CSSDeclaration(declaration) = child {
+                if let Some(span) = &declaration.span {
+                    output.add_mapping(span);
+                }
+                output.push_str(&format!("{}:{}", declaration.property, declaration.value));
+            }
+        }
+        output.push('}');
+    }
+}
+
+/// Indented output with one declaration per line.
+#[derive(Clone, Eq, PartialEq, Debug)]
+pub struct PrettySerializer {
+    pub indent: String,
+}
+
+impl Default for PrettySerializer {
+    fn default() -> Self {
+        Self {
+            indent: "  ".to_owned(),
+        }
+    }
+}
+
+impl Serializer for PrettySerializer {
+    fn write_indent(&self, depth: usize, output: &mut Output) {
+        output.push_str(&self.indent.repeat(depth));
+    }
+
+    fn write_declaration(&self, declaration: &CSSDeclaration, _depth: usize, output: &mut Output) {
+        output.push_str(&format!(
+            "{}: {};\n",
+            declaration.property, declaration.value
+        ));
+    }
+
+    fn write_rule(&self, rule: &CSSRule, depth: usize, output: &mut Output) {
+        if rule.get_children().is_empty() {
+            output.push_str(&format!("{} {{}}\n", rule.selector));
+            return;
+        }
+        output.push_str(&format!("{} {{\n", rule.selector));
+        self.write_nodes(rule.get_children(), depth + 1, output);
+        self.write_indent(depth, output);
+        output.push_str("}\n");
+    }
+
+    fn write_at_rule(&self, at_rule: &CSSAtRule, depth: usize, output: &mut Output) {
+        output.push_str(&format!("@{}", at_rule.name));
+        if let Some(params) = &at_rule.params {
+            output.push_str(&format!(" {}", params));
+        }
+        if at_rule.get_children().is_empty() {
+            output.push_str(";\n");
+            return;
+        }
+        output.push_str(" {\n");
+        self.write_nodes(at_rule.get_children(), depth + 1, output);
+        self.write_indent(depth, output);
+        output.push_str("}\n");
+    }
+}
+
+/// The smallest output: shortened values and selectors, no trailing
+/// semicolons and no empty rules.
+#[derive(Clone, Copy, Eq, PartialEq, Debug, Default)]
+pub struct MinifiedSerializer;
+
+impl MinifiedSerializer {
+    /// Declarations are separated rather than terminated by semicolons.
+    fn write_block(&self, children: &[CSSNode], depth: usize, output: &mut Output) {
+        let mut after_declaration = false;
+        for child in children {
+            let start = output.len();
+            if after_declaration {
+                output.push(';');
+            }
+            let child_start = output.len();
+            self.write_node(child, depth, output);
+            if output.len() == child_start {
+                output.truncate(start);
+                continue;
+            }
+            after_declaration = matches!(child, CSSNode::CSSDeclaration(_));
+        }
+    }
+}
+
+impl Serializer for MinifiedSerializer {
+    fn write_declaration(&self, declaration: &CSSDeclaration, _depth: usize, output: &mut Output) {
+        // custom properties are kept as written, their value being arbitrary
+        let value = match declaration.property.starts_with("--") {
+            true => declaration.value.trim().to_owned(),
+            false => minify_value(&declaration.property, &declaration.value),
+        };
+        output.push_str(&format!("{}:{}", declaration.property, value));
+    }
+
+    fn write_rule(&self, rule: &CSSRule, depth: usize, output: &mut Output) {
+        let start = output.len();
+        output.push_str(&minify_selector(&rule.selector));
+        output.push('{');
+        let block_start = output.len();
+        self.write_block(rule.get_children(), depth + 1, output);
+        if output.len() == block_start {
+            output.truncate(start);
+            return;
+        }
+        output.push('}');
+    }
+
+    fn write_at_rule(&self, at_rule: &CSSAtRule, depth: usize, output: &mut Output) {
+        let start = output.len();
+        output.push_str(&format!("@{}", at_rule.name));
+        if let Some(params) = &at_rule.params {
+            output.push_str(&format!(" {}", collapse_whitespace(params)));
+        }
+        if at_rule.get_children().is_empty() {
+            output.push(';');
+            return;
+        }
+        output.push('{');
+        let block_start = output.len();
+        self.write_block(at_rule.get_children(), depth + 1, output);
+        if output.len() == block_start {
+            output.truncate(start);
+            return;
+        }
+        output.push('}');
+    }
+}
+
+/// Output formats of the stylesheets.
+#[derive(Clone, Copy, Eq, PartialEq, Debug, Default)]
+pub enum Format {
+    #[default]
+    Compact,
+    Pretty,
+    Minified,
+}
+
+impl FromStr for Format {
+    type Err = String;
+
+    fn from_str(format: &str) -> Result<Self, Self::Err> {
+        match format.to_ascii_lowercase().as_str() {
+            "compact" => Ok(Format::Compact),
+            "pretty" => Ok(Format::Pretty),
+            "minified" => Ok(Format::Minified),
+            _ => Err(format!("Unknown format `{}`", format)),
+        }
+    }
+}
+
+impl Format {
+    pub fn get_serializer(&self) -> Box<dyn Serializer> {
+        match self {
+            Format::Compact => Box::new(CompactSerializer),
+            Format::Pretty => Box::new(PrettySerializer::default()),
+            Format::Minified => Box::new(MinifiedSerializer),
+        }
+    }
+}
+
+/// Calls `f` with the runs of `text` outside of quotes, copying quoted
+/// strings as they are.
+fn map_unquoted(text: &str, f: impl Fn(&str) -> String) -> String {
+    let mut result = String::new();
+    let mut run = String::new();
+    let mut quote = None;
+    for c in text.chars() {
+        match quote {
+            Some(q) => {
+                result.push(c);
+                if c == q {
+                    quote = None;
+                }
+            }
+            None if c == '"' || c == '\'' => {
+                result.push_str(&f(&run));
+                run.clear();
+                result.push(c);
+                quote = Some(c);
+            }
+            None => run.push(c),
+        }
+    }
+    result.push_str(&f(&run));
+    result
+}
+
+pub(crate) fn collapse_whitespace(text: &str) -> String {
+    map_unquoted(text.trim(), |run| {
+        let words: Vec<&str> = run.split_whitespace().collect();
+        if words.is_empty() {
+            return run.chars().take(1).map(|_| ' ').collect();
+        }
+        let mut collapsed = words.join(" ");
+        if run.starts_with(char::is_whitespace) {
+            collapsed.insert(0, ' ');
+        }
+        if run.ends_with(char::is_whitespace) {
+            collapsed.push(' ');
+        }
+        collapsed
+    })
+}
+
+/// Removes the whitespace around combinators and commas, e.g. `a > b, c` is
+/// `a>b,c`.
+pub fn minify_selector(selector: &str) -> String {
+    map_unquoted(&collapse_whitespace(selector), |run| {
+        let mut minified = String::new();
+        let chars: Vec<char> = run.chars().collect();
+        for (i, c) in chars.iter().enumerate() {
+            let is_separator = |c: Option<&char>| matches!(c, Some('>' | '+' | '~' | ','));
+            if *c == ' '
+                && (is_separator(chars.get(i + 1))
+                    || is_separator(minified.chars().last().as_ref()))
+            {
+                continue;
+            }
+            minified.push(*c);
+        }
+        minified
+    })
+}
+
+/// Properties taking lengths only, where a zero length is the same without
+/// its unit. Elsewhere the unit may matter, e.g. `flex: 1 0px`.
+const LENGTH_PROPERTIES: &[&str] = &[
+    "margin",
+    "padding",
+    "inset",
+    "top",
+    "right",
+    "bottom",
+    "left",
+    "width",
+    "height",
+    "min-width",
+    "max-width",
+    "min-height",
+    "max-height",
+    "gap",
+    "row-gap",
+    "column-gap",
+    "border-radius",
+];
+
+fn is_length_property(property: &str) -> bool {
+    LENGTH_PROPERTIES.iter().any(|length_property| {
+        property == *length_property
+            || property
+                .strip_prefix(length_property)
+                .is_some_and(|side| side.starts_with('-'))
+    })
+}
+
+/// Shortens a declaration value: collapsed whitespace, no space after
+/// commas, `#ffffff` as `#fff`, `0.5` as `.5` and, for length properties,
+/// zero lengths without unit outside of functions, e.g. `calc()` requiring
+/// it. `url()` arguments are kept as they are.
+pub fn minify_value(property: &str, value: &str) -> String {
+    let is_length = is_length_property(property);
+    map_unquoted(&collapse_whitespace(value), |run| {
+        let mut minified = String::new();
+        let mut word = String::new();
+        let mut depth: usize = 0;
+        let mut in_url = false;
+        for c in run.chars() {
+            if in_url {
+                minified.push(c);
+                if c == ')' {
+                    in_url = false;
+                    depth = usize::saturating_sub(depth, 1);
+                }
+            } else if matches!(c, ' ' | ',' | '(' | ')' | '/') {
+                in_url = c == '(' && word.eq_ignore_ascii_case("url");
+                minified.push_str(&minify_word(&word, is_length && depth == 0));
+                word.clear();
+                match c {
+                    '(' => depth += 1,
+                    ')' => depth = usize::saturating_sub(depth, 1),
+                    ',' if minified.ends_with(' ') => {
+                        minified.pop();
+                    }
+                    ' ' if minified.ends_with(',') => continue,
+                    _ => {}
+                }
+                minified.push(c);
+            } else {
+                word.push(c);
+            }
+        }
+        minified.push_str(&minify_word(&word, is_length && depth == 0));
+        minified
+    })
+}
+
+fn minify_word(word: &str, strip_zero_unit: bool) -> String {
+    if let Some(hex) = word.strip_prefix('#') {
+        let digits: Vec<char> = hex.chars().collect();
+        let is_short = (digits.len() == 6 || digits.len() == 8)
+            && digits.iter().all(char::is_ascii_hexdigit)
+            && digits
+                .chunks(2)
+                .all(|pair| pair[0].eq_ignore_ascii_case(&pair[1]));
+        if is_short {
+            let short: String = digits.chunks(2).map(|pair| pair[0]).collect();
+            return format!("#{}", short.to_ascii_lowercase());
+        }
+        return word.to_owned();
+    }
+
+    let (sign, number) = match word.strip_prefix('-') {
+        Some(number) => ("-", number),
+        None => ("", word),
+    };
+    let digits = number
+        .find(|c: char| !c.is_ascii_digit() && c != '.')
+        .unwrap_or(number.len());
+    let (number, unit) = number.split_at(digits);
+    let Ok(parsed) = number.parse::<f64>() else {
+        return word.to_owned();
+    };
+
+    if parsed == 0.0 && strip_zero_unit && matches!(unit, "px" | "em" | "rem" | "") {
+        return "0".to_owned();
+    }
+    let mut number = number.to_owned();
+    if number.contains('.') {
+        number = number
+            .trim_end_matches('0')
+            .trim_end_matches('.')
+            .to_owned();
+    }
+    let number = match number.strip_prefix('0') {
+        Some(fraction) if fraction.starts_with('.') => fraction,
+        // e.g. `.0s`, all of which was trimmed
+        _ if number.is_empty() => "0",
+        _ => &number,
+    };
+    format!("{}{}{}", sign, number, unit)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn get_nodes() -> Vec<CSSNode> {
+        vec![
+            CSSNode::CSSRule(
+                CSSRule::new(".a > .b,  .c")
+                    .with_declaration(CSSDeclaration::new("color", "#FFFFFF"))
+                    .with_declaration(CSSDeclaration::new("margin", "0px 0.50em"))
+                    .with_rule(
+                        CSSRule::new("&:hover").with_declaration(CSSDeclaration::new(
+                            "font-family",
+                            "\"Helvetica  Neue\", sans-serif",
+                        )),
+                    ),
+            ),
+            CSSNode::CSSAtRule(
+                CSSAtRule::new("media", Some("(min-width:  768px)"))
+                    .with_rule(CSSRule::new(".d"))
+                    .with_rule(
+                        CSSRule::new(".e")
+                            .with_declaration(CSSDeclaration::new("width", "calc(100% - 0px)")),
+                    ),
+            ),
+            CSSNode::CSSAtRule(CSSAtRule::new("import", Some("url(a.css)"))),
+        ]
+    }
+
+    #[test]
+    fn it_serializes_compact_css() {
+        let css: String = get_nodes().iter().map(|node| node.get_css()).collect();
+        assert_eq!(CompactSerializer.serialize_nodes(&get_nodes(), 0), css);
+    }
+
+    #[test]
+    fn it_serializes_pretty_css() {
+        assert_eq!(
+            PrettySerializer::default().serialize_nodes(&get_nodes(), 0),
+            r#".a > .b,  .c {
+  color: #FFFFFF;
+  margin: 0px 0.50em;
+  &:hover {
+    font-family: "Helvetica  Neue", sans-serif;
+  }
+}
+@media (min-width:  768px) {
+  .d {}
+  .e {
+    width: calc(100% - 0px);
+  }
+}
+@import url(a.css);
+"#
+        );
+    }
+
+    #[test]
+    fn it_serializes_minified_css() {
+        assert_eq!(
+            MinifiedSerializer.serialize_nodes(&get_nodes(), 0),
+            ".a>.b,.c{color:#fff;margin:0 .5em;&:hover{font-family:\"Helvetica  Neue\",sans-serif}}\
+             @media (min-width: 768px){.e{width:calc(100% - 0px)}}@import url(a.css);"
+        );
+    }
+
+    #[test]
+    fn it_minifies_values_safely() {
+        assert_eq!(minify_value("flex", "1 0px"), "1 0px");
+        assert_eq!(minify_value("margin-top", "0px"), "0");
+        assert_eq!(
+            minify_value("transition", "opacity .0s 0.50s"),
+            "opacity 0s .5s"
+        );
+        assert_eq!(
+            minify_value("fill", "url(#aabbcc) #AABBCC"),
+            "url(#aabbcc) #abc"
+        );
+    }
+
+    #[test]
+    fn it_parses_formats() {
+        assert_eq!("Pretty".parse::<Format>(), Ok(Format::Pretty));
+        assert!("dense".parse::<Format>().is_err());
+    }
+}
@@ -0,0 +1,147 @@
+use serde_json::json;
+
+use crate::serializer::*;
+
+const BASE64: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+/// Appends `value` as a base64 VLQ, as used by the source map mappings.
+fn encode_vlq(value: i64, encoded: &mut String) {
+    let mut vlq = match value < 0 {
+        true => ((-value) << 1) | 1,
+        false => value << 1,
+    };
+    loop {
+        let mut digit = vlq & 0b11111;
+        vlq >>= 5;
+        if vlq > 0 {
+            digit |= 0b100000;
+        }
+        encoded.push(BASE64[digit as usize] as char);
+        if vlq == 0 {
+            break;
+        }
+    }
+}
+
+/// Zero based line and column of the byte `offset` of `text`, the column
+/// counted in UTF-16 code units as source maps expect.
+struct LineIndex<'a> {
+    text: &'a str,
+    line_starts: Vec<usize>,
+}
+
+impl<'a> LineIndex<'a> {
+    fn new(text: &'a str) -> Self {
+        let line_starts = std::iter::once(0)
+            .chain(text.match_indices('\n').map(|(offset, _)| offset + 1))
+            .collect();
+        Self { text, line_starts }
+    }
+
+    fn get_position(&self, offset: usize) -> (usize, usize) {
+        let line = match self.line_starts.binary_search(&offset) {
+            Ok(line) => line,
+            Err(line) => line - 1,
+        };
+        let line_start = self.line_starts[line];
+        let column = self
+            .text
+            .get(line_start..offset)
+            .map_or(0, |prefix| prefix.encode_utf16().count());
+        (line, column)
+    }
+}
+
+/// Source Map v3 of `output`, written from the single `source` file, e.g. the
+/// DSL file the organism was run from. `file` is the name of the stylesheet.
+pub fn get_source_map(output: &Output, file: &str, source_name: &str, source: &str) -> String {
+    let generated = LineIndex::new(&output.css);
+    let original = LineIndex::new(source);
+
+    let mut mappings = String::new();
+    let mut generated_line = 0;
+    let mut previous_column = 0;
+    let mut previous_original = (0, 0);
+    let mut previous_offset = None;
+
+    for (offset, span) in &output.mappings {
+        // nested nodes starting at the same offset map to the outermost one
+        if previous_offset == Some(*offset) || span.start > source.len() {
+            continue;
+        }
+        previous_offset = Some(*offset);
+
+        let (line, column) = generated.get_position(*offset);
+        if line > generated_line {
+            mappings.push_str(&";".repeat(line - generated_line));
+            generated_line = line;
+            previous_column = 0;
+        } else if !mappings.is_empty() && !mappings.ends_with(';') {
+            mappings.push(',');
+        }
+
+        let (original_line, original_column) = original.get_position(span.start);
+        encode_vlq(column as i64 - previous_column as i64, &mut mappings);
+        encode_vlq(0, &mut mappings);
+        encode_vlq(
+            original_line as i64 - previous_original.0 as i64,
+            &mut mappings,
+        );
+        encode_vlq(
+            original_column as i64 - previous_original.1 as i64,
+            &mut mappings,
+        );
+        previous_column = column;
+        previous_original = (original_line, original_column);
+    }
+
+    json!({
+        "version": 3,
+        "file": file,
+        "sources": [source_name],
+        "sourcesContent": [source],
+        "names": [],
+        "mappings": mappings,
+    })
+    .to_string()
+}
+
+/// Comment pointing browsers to the source map of a stylesheet.
+pub fn get_source_map_comment(source_map_url: &str) -> String {
+    format!("/*# sourceMappingURL={} */", source_map_url)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::css::*;
+
+    #[test]
+    fn it_encodes_vlq() {
+        let mut encoded = String::new();
+        for value in [0, 1, -1, 15, 16, -17, 1000] {
+            encode_vlq(value, &mut encoded);
+        }
+        assert_eq!(encoded, "ACDegBjBw+B");
+    }
+
+    #[test]
+    fn it_maps_nodes_to_their_source() {
+        let source = "(& `.a`\n  (color `red`)\n  (margin `0`))";
+        let rule = CSSRule::new(".a")
+            .with_span(0..38)
+            .with_declaration(CSSDeclaration::new("color", "red").with_span(10..23))
+            .with_declaration(CSSDeclaration::new("margin", "0").with_span(26..38));
+
+        let mut output = Output::default();
+        PrettySerializer::default().write_nodes(&[CSSNode::CSSRule(rule)], 0, &mut output);
+        assert_eq!(output.css, ".a {\n  color: red;\n  margin: 0;\n}\n");
+
+        let source_map: serde_json::Value =
+            serde_json::from_str(&get_source_map(&output, "a.css", "a.lisp", source)).unwrap();
+        assert_eq!(source_map["version"], 3);
+        assert_eq!(source_map["sources"][0], "a.lisp");
+        // `.a`, `color` and `margin` at the same positions as in the source
+        assert_eq!(source_map["mappings"], "AAAA;EACE;EACA");
+    }
+}
@@ -0,0 +1,122 @@
+use derive_more::Display;
+
+use crate::css::*;
+use crate::token::*;
+
+pub type ThemeName = String;
+
+/// A set of token overrides applied through CSS custom properties whenever
+/// one of the theme selectors or media queries matches.
+#[derive(Clone, Eq, PartialEq, Debug, Default, Display)]
+#[display(fmt = "#Theme({})", name)]
+pub struct Theme {
+    pub name: ThemeName,
+    pub selectors: Vec<String>,
+    pub media: Vec<String>,
+    pub tokens: Vec<Token>,
+}
+
+impl Theme {
+    pub fn new(name: &str) -> Self {
+        Self {
+            name: name.to_string(),
+            ..Default::default()
+        }
+    }
+
+    pub fn with_selector(mut self, selector: &str) -> Self {
+        self.insert_selector(selector);
+        self
+    }
+
+    pub fn with_media(mut self, media: &str) -> Self {
+        self.insert_media(media);
+        self
+    }
+
+    pub fn with_token(mut self, token: Token) -> Self {
+        self.insert_token(&token);
+        self
+    }
+
+    pub fn insert_selector(&mut self, selector: &str) {
+        self.selectors.push(selector.to_string());
+    }
+
+    pub fn insert_media(&mut self, media: &str) {
+        self.media.push(media.to_string());
+    }
+
+    pub fn insert_token(&mut self, token: &Token) {
+        self.tokens.retain(|t| t.name != token.name);
+        self.tokens.push(token.clone());
+    }
+
+    /// Custom properties rules for every selector and media query, none
+    /// without tokens.
+    pub fn get_nodes(&self) -> Vec<CSSNode> {
+        let mut nodes = vec![];
+        if self.tokens.is_empty() {
+            return nodes;
+        }
+
+        for selector in &self.selectors {
+            nodes.push(CSSNode::CSSRule(get_custom_properties_rule(
+                selector,
+                &self.tokens,
+            )));
+        }
+        for media in &self.media {
+            nodes.push(CSSNode::CSSAtRule(
+                CSSAtRule::new("media", Some(media))
+                    .with_rule(get_custom_properties_rule(":root", &self.tokens)),
+            ));
+        }
+
+        nodes
+    }
+
+    pub fn get_css(&self) -> String {
+        self.get_nodes().iter().map(|node| node.get_css()).collect()
+    }
+}
+
+pub fn get_custom_properties_rule<'a>(
+    selector: &str,
+    tokens: impl IntoIterator<Item = &'a Token>,
+) -> CSSRule {
+    let mut rule = CSSRule::new(selector);
+    for token in tokens {
+        rule.insert_declaration(&CSSDeclaration::new(
+            &token.get_custom_property(),
+            &token.get_css_value(),
+        ));
+    }
+    rule
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_emits_theme_blocks() {
+        let theme = Theme::new("dark")
+            .with_selector("[data-theme=dark]")
+            .with_media("(prefers-color-scheme: dark)")
+            .with_token(Token::new("color.text", "#ffffff"))
+            .with_token(Token::new("color.brand", "{color.base.red}"));
+
+        assert_eq!(
+            theme.get_css(),
+            "[data-theme=dark]{--color-text:#ffffff;--color-brand:var(--color-base-red);}\
+             @media (prefers-color-scheme: dark){:root{--color-text:#ffffff;--color-brand:var(--color-base-red);}}"
+        );
+    }
+
+    #[test]
+    fn it_skips_empty_themes() {
+        let theme = Theme::new("dark").with_selector("[data-theme=dark]");
+        assert_eq!(theme.get_css(), "");
+    }
+}
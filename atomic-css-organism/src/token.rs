@@ -0,0 +1,138 @@
+use derive_more::Display;
+use regex::Regex;
+use std::collections::HashMap;
+
+pub type TokenName = String;
+
+#[derive(Clone, Eq, PartialEq, Debug, Default, Display)]
+#[display(fmt = "#Token({})", name)]
+pub struct Token {
+    /// Dot separated path of the token, e.g. `color.brand.primary`.
+    pub name: TokenName,
+    pub token_type: Option<String>,
+    /// Raw value, possibly containing `{alias.references}` to other tokens.
+    pub value: String,
+    pub description: Option<String>,
+}
+
+impl Token {
+    pub fn new(name: &str, value: &str) -> Self {
+        Self {
+            name: name.to_string(),
+            value: value.to_string(),
+            ..Default::default()
+        }
+    }
+
+    pub fn with_type(mut self, token_type: &str) -> Self {
+        self.token_type = Some(token_type.to_string());
+        self
+    }
+
+    pub fn with_description(mut self, description: &str) -> Self {
+        self.description = Some(description.to_string());
+        self
+    }
+
+    pub fn get_aliases(&self) -> Vec<TokenName> {
+        let re = Regex::new(r"\{([^{}]+)\}").unwrap();
+        re.captures_iter(&self.value)
+            .map(|cap| cap[1].to_string())
+            .collect()
+    }
+
+    pub fn get_custom_property(&self) -> String {
+        get_custom_property(&self.name)
+    }
+
+    /// Value of the custom property, aliases referencing the custom property
+    /// of the aliased token so that theme overrides cascade through them.
+    pub fn get_css_value(&self) -> String {
+        let mut value = self.value.clone();
+        for alias in self.get_aliases() {
+            value = value.replace(
+                &format!("{{{}}}", alias),
+                &format!("var({})", get_custom_property(&alias)),
+            );
+        }
+        value
+    }
+}
+
+/// Name of the CSS custom property backing a token, e.g. `--color-brand` for
+/// `color.brand`.
+pub fn get_custom_property(token: &str) -> String {
+    format!("--{}", token.replace('.', "-"))
+}
+
+fn resolve(
+    tokens: &HashMap<TokenName, Token>,
+    name: &str,
+    stack: &mut Vec<TokenName>,
+) -> Result<String, String> {
+    if stack.iter().any(|visited| visited == name) {
+        stack.push(name.to_string());
+        return Err(format!("Circular token alias {}", stack.join(" -> ")));
+    }
+    let Some(token) = tokens.get(name) else {
+        return Err(format!("Unknown token `{}`", name));
+    };
+
+    stack.push(name.to_string());
+    let mut value = token.value.clone();
+    for alias in token.get_aliases() {
+        let resolved = resolve(tokens, &alias, stack)?;
+        value = value.replace(&format!("{{{}}}", alias), &resolved);
+    }
+    stack.pop();
+
+    Ok(value)
+}
+
+/// Resolves the value of a token, following its aliases.
+pub fn resolve_token(tokens: &HashMap<TokenName, Token>, name: &str) -> Result<String, String> {
+    resolve(tokens, name, &mut vec![])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tokens(tokens: Vec<Token>) -> HashMap<TokenName, Token> {
+        tokens
+            .into_iter()
+            .map(|token| (token.name.clone(), token))
+            .collect()
+    }
+
+    #[test]
+    fn it_resolves_aliases() {
+        let tokens = tokens(vec![
+            Token::new("color.red", "#ff0000"),
+            Token::new("color.brand", "{color.red}"),
+            Token::new("border.brand", "1px solid {color.brand}"),
+        ]);
+        assert_eq!(resolve_token(&tokens, "color.brand"), Ok("#ff0000".into()));
+        assert_eq!(
+            resolve_token(&tokens, "border.brand"),
+            Ok("1px solid #ff0000".into())
+        );
+        assert!(resolve_token(&tokens, "color.blue").is_err());
+    }
+
+    #[test]
+    fn it_references_aliases_as_custom_properties() {
+        let token = Token::new("border.brand", "1px solid {color.brand}");
+        assert_eq!(token.get_custom_property(), "--border-brand");
+        assert_eq!(token.get_css_value(), "1px solid var(--color-brand)");
+    }
+
+    #[test]
+    fn it_detects_circular_aliases() {
+        let tokens = tokens(vec![Token::new("a", "{b}"), Token::new("b", "{a}")]);
+        assert_eq!(
+            resolve_token(&tokens, "a"),
+            Err("Circular token alias a -> b -> a".into())
+        );
+    }
+}
@@ -0,0 +1,244 @@
+use derive_more::Display;
+use std::str::FromStr;
+
+use crate::css::*;
+use crate::electron::*;
+
+pub type ConditionName = String;
+
+/// A named at-rule condition, i.e. a media query breakpoint, a container
+/// query or a feature query, referenced as the first prefix of an electron
+/// name, e.g. `md:bg_green`.
+#[derive(Clone, Eq, PartialEq, Debug, Default, Display)]
+#[display(fmt = "#Condition(@{} {})", at_rule, params)]
+pub struct Condition {
+    pub name: ConditionName,
+    pub at_rule: String,
+    pub params: String,
+}
+
+impl Condition {
+    pub fn new(name: &str, at_rule: &str, params: &str) -> Self {
+        Self {
+            name: name.to_string(),
+            at_rule: at_rule.to_string(),
+            params: params.to_string(),
+        }
+    }
+
+    pub fn breakpoint(name: &str, media: &str) -> Self {
+        Self::new(name, "media", media)
+    }
+
+    pub fn container(name: &str, container: Option<&str>, query: &str) -> Self {
+        match container {
+            Some(container) => Self::new(name, "container", &format!("{} {}", container, query)),
+            None => Self::new(name, "container", query),
+        }
+    }
+
+    pub fn supports(name: &str, query: &str) -> Self {
+        Self::new(name, "supports", query)
+    }
+
+    pub fn get_at_rule(&self) -> CSSAtRule {
+        CSSAtRule::new(&self.at_rule, Some(&self.params))
+    }
+}
+
+/// State in which an electron applies, referenced as a prefix of the electron
+/// name, e.g. `hover:red`. Variant rules are emitted in declaration order, so
+/// that `active` wins over `hover` when both match.
+#[derive(Clone, Copy, Eq, PartialEq, Ord, PartialOrd, Hash, Debug, Display)]
+pub enum Variant {
+    #[display(fmt = "hover")]
+    Hover,
+    #[display(fmt = "group-hover")]
+    GroupHover,
+    #[display(fmt = "focus-visible")]
+    FocusVisible,
+    #[display(fmt = "active")]
+    Active,
+    #[display(fmt = "disabled")]
+    Disabled,
+}
+
+impl FromStr for Variant {
+    type Err = String;
+
+    fn from_str(variant: &str) -> Result<Self, Self::Err> {
+        match variant {
+            "hover" => Ok(Variant::Hover),
+            "group-hover" => Ok(Variant::GroupHover),
+            "focus-visible" => Ok(Variant::FocusVisible),
+            "active" => Ok(Variant::Active),
+            "disabled" => Ok(Variant::Disabled),
+            _ => Err(format!("Unknown variant `{}`", variant)),
+        }
+    }
+}
+
+impl Variant {
+    pub fn apply(&self, selector: &str) -> String {
+        match self {
+            Variant::Hover => format!("{}:hover", selector),
+            Variant::GroupHover => format!(".group:hover {}", selector),
+            Variant::FocusVisible => format!("{}:focus-visible", selector),
+            Variant::Active => format!("{}:active", selector),
+            Variant::Disabled => format!("{}:disabled", selector),
+        }
+    }
+}
+
+/// Escapes a class name so that it can be used in a selector, e.g.
+/// `hover:red` becomes `hover\:red`.
+pub fn escape_class(class: &str) -> String {
+    let mut escaped = String::new();
+    for c in class.chars() {
+        if !(c.is_ascii_alphanumeric() || c == '-' || c == '_' || !c.is_ascii()) {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+/// An electron as referenced by an atom, along with its condition and
+/// variants.
+#[derive(Clone, Eq, PartialEq, Hash, Debug, Display)]
+#[display(fmt = "{}", "self.get_class()")]
+pub struct ElectronReference {
+    pub condition: Option<ConditionName>,
+    pub variants: Vec<Variant>,
+    pub electron: ElectronName,
+}
+
+impl ElectronReference {
+    pub fn new(electron: &str) -> Self {
+        Self {
+            condition: None,
+            variants: vec![],
+            electron: electron.to_string(),
+        }
+    }
+
+    pub fn with_condition(mut self, condition: &str) -> Self {
+        self.condition = Some(condition.to_string());
+        self
+    }
+
+    pub fn with_variant(mut self, variant: Variant) -> Self {
+        self.variants.push(variant);
+        self
+    }
+
+    pub fn parse(reference: &str) -> Result<Self, String> {
+        let mut parts: Vec<&str> = reference.split(':').collect();
+        let electron = parts.pop().unwrap_or_default();
+        if electron.is_empty() {
+            return Err(format!("Invalid electron reference `{}`", reference));
+        }
+
+        let mut electron_reference = Self::new(electron);
+        for (i, part) in parts.into_iter().enumerate() {
+            // any prefix which is not a variant is a condition, which must come first
+            let variant = match part.parse::<Variant>() {
+                Ok(variant) => variant,
+                Err(_) if i == 0 && !part.is_empty() => {
+                    electron_reference.condition = Some(part.to_string());
+                    continue;
+                }
+                Err(error) => return Err(error),
+            };
+            if electron_reference.variants.contains(&variant) {
+                return Err(format!("Duplicated variant `{}`", variant));
+            }
+            electron_reference.variants.push(variant);
+        }
+        Ok(electron_reference)
+    }
+
+    pub fn get_class(&self) -> String {
+        let mut class = String::new();
+        if let Some(condition) = &self.condition {
+            class.push_str(&format!("{}:", condition));
+        }
+        for variant in &self.variants {
+            class.push_str(&format!("{}:", variant));
+        }
+        class.push_str(&self.electron);
+        class
+    }
+
+    pub fn get_selector(&self) -> String {
+        let mut selector = format!(".{}", escape_class(&self.get_class()));
+        for variant in &self.variants {
+            selector = variant.apply(&selector);
+        }
+        selector
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_parses_references() {
+        assert_eq!(
+            ElectronReference::parse("hover:red"),
+            Ok(ElectronReference::new("red").with_variant(Variant::Hover))
+        );
+        assert_eq!(
+            ElectronReference::parse("red"),
+            Ok(ElectronReference::new("red"))
+        );
+        assert_eq!(
+            ElectronReference::parse("md:hover:red"),
+            Ok(ElectronReference::new("red")
+                .with_condition("md")
+                .with_variant(Variant::Hover))
+        );
+        assert!(ElectronReference::parse("hover:visited:red").is_err());
+        assert!(ElectronReference::parse("hover:md:red").is_err());
+        assert!(ElectronReference::parse("hover:hover:red").is_err());
+        assert!(ElectronReference::parse("hover:").is_err());
+    }
+
+    #[test]
+    fn it_builds_condition_at_rules() {
+        assert_eq!(
+            Condition::breakpoint("md", "(min-width: 768px)").get_at_rule(),
+            CSSAtRule::new("media", Some("(min-width: 768px)"))
+        );
+        assert_eq!(
+            Condition::container("card-md", Some("card"), "(min-width: 400px)").get_at_rule(),
+            CSSAtRule::new("container", Some("card (min-width: 400px)"))
+        );
+        assert_eq!(
+            Condition::supports("grid", "(display: grid)").get_at_rule(),
+            CSSAtRule::new("supports", Some("(display: grid)"))
+        );
+    }
+
+    #[test]
+    fn it_builds_variant_selectors() {
+        let reference = ElectronReference::parse("group-hover:focus-visible:red").unwrap();
+        assert_eq!(
+            reference.get_selector(),
+            ".group:hover .group-hover\\:focus-visible\\:red:focus-visible"
+        );
+        assert_eq!(
+            ElectronReference::parse("disabled:red")
+                .unwrap()
+                .get_selector(),
+            ".disabled\\:red:disabled"
+        );
+        assert_eq!(
+            ElectronReference::parse("md:hover:red")
+                .unwrap()
+                .get_selector(),
+            ".md\\:hover\\:red:hover"
+        );
+    }
+}
@@ -0,0 +1,155 @@
+use crate::css::*;
+
+/// Walks a css tree, e.g. for lint passes. Every method walks the children of
+/// the node by default, so that implementations only override the nodes
+/// they are interested in, calling the matching `walk_*` function to keep
+/// going deeper.
+pub trait Visitor {
+    fn visit_nodes(&mut self, nodes: &[CSSNode]) {
+        walk_nodes(self, nodes);
+    }
+
+    fn visit_node(&mut self, node: &CSSNode) {
+        walk_node(self, node);
+    }
+
+    fn visit_rule(&mut self, rule: &CSSRule) {
+        walk_rule(self, rule);
+    }
+
+    fn visit_at_rule(&mut self, at_rule: &CSSAtRule) {
+        walk_at_rule(self, at_rule);
+    }
+
+    fn visit_declaration(&mut self, _declaration: &CSSDeclaration) {}
+}
+
+pub fn walk_nodes<V: Visitor + ?Sized>(visitor: &mut V, nodes: &[CSSNode]) {
+    for node in nodes {
+        visitor.visit_node(node);
+    }
+}
+
+pub fn walk_node<V: Visitor + ?Sized>(visitor: &mut V, node: &CSSNode) {
+    match node {
+        CSSNode::CSSRule(rule) => visitor.visit_rule(rule),
+        CSSNode::CSSAtRule(at_rule) => visitor.visit_at_rule(at_rule),
+        CSSNode::CSSDeclaration(declaration) => visitor.visit_declaration(declaration),
+    }
+}
+
+pub fn walk_rule<V: Visitor + ?Sized>(visitor: &mut V, rule: &CSSRule) {
+    visitor.visit_nodes(rule.get_children());
+}
+
+pub fn walk_at_rule<V: Visitor + ?Sized>(visitor: &mut V, at_rule: &CSSAtRule) {
+    visitor.visit_nodes(at_rule.get_children());
+}
+
+/// Same as `Visitor` with mutable access to the tree, e.g. for optimization
+/// passes. `visit_nodes_mut` receives the children of a node, so that nodes
+/// can be removed, replaced or inserted.
+pub trait VisitorMut {
+    fn visit_nodes_mut(&mut self, nodes: &mut Vec<CSSNode>) {
+        walk_nodes_mut(self, nodes);
+    }
+
+    fn visit_node_mut(&mut self, node: &mut CSSNode) {
+        walk_node_mut(self, node);
+    }
+
+    fn visit_rule_mut(&mut self, rule: &mut CSSRule) {
+        walk_rule_mut(self, rule);
+    }
+
+    fn visit_at_rule_mut(&mut self, at_rule: &mut CSSAtRule) {
+        walk_at_rule_mut(self, at_rule);
+    }
+
+    fn visit_declaration_mut(&mut self, _declaration: &mut CSSDeclaration) {}
+}
+
+pub fn walk_nodes_mut<V: VisitorMut + ?Sized>(visitor: &mut V, nodes: &mut [CSSNode]) {
+    for node in nodes.iter_mut() {
+        visitor.visit_node_mut(node);
+    }
+}
+
+pub fn walk_node_mut<V: VisitorMut + ?Sized>(visitor: &mut V, node: &mut CSSNode) {
+    match node {
+        CSSNode::CSSRule(rule) => visitor.visit_rule_mut(rule),
+        CSSNode::CSSAtRule(at_rule) => visitor.visit_at_rule_mut(at_rule),
+        CSSNode::CSSDeclaration(declaration) => visitor.visit_declaration_mut(declaration),
+    }
+}
+
+pub fn walk_rule_mut<V: VisitorMut + ?Sized>(visitor: &mut V, rule: &mut CSSRule) {
+    visitor.visit_nodes_mut(rule.get_children_mut());
+}
+
+pub fn walk_at_rule_mut<V: VisitorMut + ?Sized>(visitor: &mut V, at_rule: &mut CSSAtRule) {
+    visitor.visit_nodes_mut(at_rule.get_children_mut());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn get_nodes() -> Vec<CSSNode> {
+        vec![
+            CSSNode::CSSRule(
+                CSSRule::new(".a")
+                    .with_declaration(CSSDeclaration::new("color", "red"))
+                    .with_rule(CSSRule::new("&:hover"))
+                    .with_at_rule(
+                        CSSAtRule::new("media", Some("print"))
+                            .with_declaration(CSSDeclaration::new("display", "none")),
+                    ),
+            ),
+            CSSNode::CSSAtRule(CSSAtRule::new("import", Some("url(a.css)"))),
+        ]
+    }
+
+    #[test]
+    fn it_visits_nodes() {
+        #[derive(Default)]
+        struct PropertyCollector(Vec<String>);
+
+        impl Visitor for PropertyCollector {
+            fn visit_declaration(&mut self, declaration: &CSSDeclaration) {
+                self.0.push(declaration.property.to_owned());
+            }
+        }
+
+        let mut collector = PropertyCollector::default();
+        collector.visit_nodes(&get_nodes());
+        assert_eq!(collector.0, vec!["color", "display"]);
+    }
+
+    #[test]
+    fn it_transforms_nodes() {
+        struct EmptyRuleRemover;
+
+        impl VisitorMut for EmptyRuleRemover {
+            fn visit_nodes_mut(&mut self, nodes: &mut Vec<CSSNode>) {
+                walk_nodes_mut(self, nodes);
+                nodes.retain(|node| match node {
+                    CSSNode::CSSRule(rule) => !rule.get_children().is_empty(),
+                    _ => true,
+                });
+            }
+
+            fn visit_declaration_mut(&mut self, declaration: &mut CSSDeclaration) {
+                declaration.value = declaration.value.to_uppercase();
+            }
+        }
+
+        let mut nodes = get_nodes();
+        EmptyRuleRemover.visit_nodes_mut(&mut nodes);
+        let css: String = nodes.iter().map(|node| node.get_css()).collect();
+        assert_eq!(
+            css,
+            ".a{color:RED;@media print{display:NONE;}}@import url(a.css);"
+        );
+    }
+}
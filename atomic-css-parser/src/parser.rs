@@ -25,16 +25,27 @@ type Result<T> = std::result::Result<T, Error>;
 
 #[derive(Clone, Eq, PartialEq, Debug, Display)]
 pub enum Node {
+    /// A function call, along with its span in the source, parentheses
+    /// included.
     #[display(fmt = "Function({}, {:?})", _0, _1)]
-    Function(String, Vec<Node>),
+    Function(String, Vec<Node>, Span),
     String(String),
     Identifier(String),
 }
 
-fn parse_function(lexer: &mut Lexer<'_, Token>) -> Result<Node> {
+/// Maximum nesting of functions, so that arbitrary input cannot overflow the
+/// stack of the parser or of the runtime evaluating the nodes.
+pub const MAX_DEPTH: usize = 64;
+
+fn parse_function(lexer: &mut Lexer<'_, Token>, depth: usize) -> Result<Node> {
+    let start = lexer.span().start;
     let id;
     let mut nodes = vec![];
 
+    if depth > MAX_DEPTH {
+        return Err(("Maximum nesting depth exceeded".to_owned(), lexer.span()));
+    }
+
     if let Some(Ok(Token::Identifier(name))) = lexer.next() {
         id = name;
     } else {
@@ -43,9 +54,15 @@ fn parse_function(lexer: &mut Lexer<'_, Token>) -> Result<Node> {
 
     while let Some(token) = lexer.next() {
         match token {
-            Ok(Token::RParen) => return Ok(Node::Function(id.to_string(), nodes)),
+            Ok(Token::RParen) => {
+                return Ok(Node::Function(
+                    id.to_string(),
+                    nodes,
+                    start..lexer.span().end,
+                ))
+            }
             Ok(Token::String(text)) => nodes.push(Node::String(text)),
-            Ok(Token::LParen) => nodes.push(parse_function(lexer)?),
+            Ok(Token::LParen) => nodes.push(parse_function(lexer, depth + 1)?),
             Ok(Token::Identifier(name)) => nodes.push(Node::Identifier(name)),
             _ => return Err(("Invalid token".to_owned(), lexer.span())),
         }
@@ -60,7 +77,7 @@ pub fn parse(source: &str) -> Result<Vec<Node>> {
 
     while let Some(token) = lexer.next() {
         if let Ok(Token::LParen) = token {
-            match parse_function(&mut lexer) {
+            match parse_function(&mut lexer, 1) {
                 Ok(function) => result.push(function),
                 Err(error) => {
                     pretty_print_error(&error, source);
@@ -92,7 +109,8 @@ mod tests {
                 vec![
                     Node::Identifier("arg1".to_string()),
                     Node::Identifier("arg2".to_string())
-                ]
+                ],
+                0..18
             )])
         );
     }
@@ -109,6 +127,24 @@ mod tests {
         assert!(result.is_err());
     }
 
+    #[test]
+    fn test_parse_max_depth() {
+        let nested = |depth| format!("{}{}", "(f ".repeat(depth), ")".repeat(depth));
+        assert!(parse(&nested(MAX_DEPTH)).is_ok());
+        assert!(parse(&nested(MAX_DEPTH + 1)).is_err());
+    }
+
+    #[test]
+    fn test_parse_spans() {
+        let result = parse("(a (b `c`)\n  (d))").unwrap();
+        let Node::Function(_, args, span) = &result[0] else {
+            panic!("Expected function");
+        };
+        assert_eq!(span, &(0..17));
+        assert!(matches!(&args[0], Node::Function(_, _, span) if span == &(3..10)));
+        assert!(matches!(&args[1], Node::Function(_, _, span) if span == &(13..16)));
+    }
+
     #[test]
     fn test_parse_empty() {
         let result = parse("");
@@ -140,7 +176,8 @@ mod tests {
             result,
             Ok(vec![Node::Function(
                 "test".to_string(),
-                vec![Node::String("valid string".to_string())]
+                vec![Node::String("valid string".to_string())],
+                0..21
             )])
         );
     }
@@ -152,7 +189,8 @@ mod tests {
             result,
             Ok(vec![Node::Function(
                 "test".to_string(),
-                vec![Node::String("invalid string".to_string())]
+                vec![Node::String("invalid string".to_string())],
+                0..21
             )])
         );
     }
@@ -162,7 +200,11 @@ mod tests {
         let result = parse("(validIdentifier)");
         assert_eq!(
             result,
-            Ok(vec![Node::Function("validIdentifier".to_string(), vec![])])
+            Ok(vec![Node::Function(
+                "validIdentifier".to_string(),
+                vec![],
+                0..17
+            )])
         );
     }
 
@@ -171,7 +213,11 @@ mod tests {
         let result = parse("(valid-identifier)");
         assert_eq!(
             result,
-            Ok(vec![Node::Function("valid-identifier".to_string(), vec![])])
+            Ok(vec![Node::Function(
+                "valid-identifier".to_string(),
+                vec![],
+                0..18
+            )])
         );
     }
 
@@ -180,20 +226,30 @@ mod tests {
         let result = parse("(@identifier)");
         assert_eq!(
             result,
-            Ok(vec![Node::Function("@identifier".to_string(), vec![])])
+            Ok(vec![Node::Function(
+                "@identifier".to_string(),
+                vec![],
+                0..13
+            )])
         );
     }
 
     #[test]
     fn test_node_identifier_ampersand() {
         let result = parse("(&)");
-        assert_eq!(result, Ok(vec![Node::Function("&".to_string(), vec![])]));
+        assert_eq!(
+            result,
+            Ok(vec![Node::Function("&".to_string(), vec![], 0..3)])
+        );
     }
 
     #[test]
     fn test_node_identifier_invalid() {
         let result = parse("(1234)");
-        assert_ne!(result, Ok(vec![Node::Function("1234".to_string(), vec![])]));
+        assert_ne!(
+            result,
+            Ok(vec![Node::Function("1234".to_string(), vec![], 0..6)])
+        );
     }
 
     #[test]
@@ -0,0 +1,102 @@
+use atomic_css_organism::color::*;
+
+fn color(value: &str) -> Result<Color, String> {
+    Color::parse(value)
+}
+
+fn with_color(args: &[String], f: impl Fn(Color) -> String) -> Result<String, String> {
+    match args {
+        [value] => Ok(f(color(value)?)),
+        _ => Err("Expected a single color argument".to_owned()),
+    }
+}
+
+fn with_color_and_amount(
+    args: &[String],
+    f: impl Fn(Color, f64) -> Color,
+) -> Result<String, String> {
+    match args {
+        [value, amount] => Ok(f(color(value)?, parse_amount(amount)?).to_hex()),
+        _ => Err("Expected a color and an amount".to_owned()),
+    }
+}
+
+fn mix(args: &[String]) -> Result<String, String> {
+    match args {
+        [first, second] => Ok(color(first)?.mix(&color(second)?, 0.5).to_hex()),
+        [first, second, weight] => Ok(color(first)?
+            .mix(&color(second)?, parse_amount(weight)?)
+            .to_hex()),
+        _ => Err("Expected two colors and an optional weight".to_owned()),
+    }
+}
+
+fn contrast_ratio(args: &[String]) -> Result<String, String> {
+    match args {
+        [first, second] => Ok(format_ratio(color(first)?.contrast_ratio(&color(second)?))),
+        _ => Err("Expected two colors".to_owned()),
+    }
+}
+
+/// Evaluates a built-in value function on its already evaluated arguments.
+/// Returns `None` when `name` is not a built-in.
+pub fn call_builtin(name: &str, args: &[String]) -> Option<Result<String, String>> {
+    let result = match name {
+        "lighten" => with_color_and_amount(args, |c, amount| c.lighten(amount)),
+        "darken" => with_color_and_amount(args, |c, amount| c.darken(amount)),
+        "alpha" => with_color_and_amount(args, |c, amount| c.with_alpha(amount)),
+        "mix" => mix(args),
+        "contrast-ratio" => contrast_ratio(args),
+        "hex" => with_color(args, |c| c.to_hex()),
+        "rgb" => with_color(args, |c| c.to_rgb()),
+        "hsl" => with_color(args, |c| c.to_hsl()),
+        "oklch" => with_color(args, |c| c.to_oklch()),
+        _ => return None,
+    };
+    Some(result)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn call(name: &str, args: &[&str]) -> Option<Result<String, String>> {
+        let args: Vec<String> = args.iter().map(|arg| arg.to_string()).collect();
+        call_builtin(name, &args)
+    }
+
+    #[test]
+    fn test_color_builtins() {
+        assert_eq!(
+            call("darken", &["#ff0000", "10%"]),
+            Some(Ok("#cc0000".into()))
+        );
+        assert_eq!(
+            call("lighten", &["#ff0000", "0.1"]),
+            Some(Ok("#ff3333".into()))
+        );
+        assert_eq!(
+            call("alpha", &["#ff0000", "40%"]),
+            Some(Ok("#ff000066".into()))
+        );
+        assert_eq!(
+            call("mix", &["#ff0000", "#0000ff"]),
+            Some(Ok("#800080".into()))
+        );
+        assert_eq!(
+            call("contrast-ratio", &["#000", "#fff"]),
+            Some(Ok("21".into()))
+        );
+        assert_eq!(
+            call("hsl", &["#ff0000"]),
+            Some(Ok("hsl(0 100% 50%)".into()))
+        );
+    }
+
+    #[test]
+    fn test_invalid_builtin_calls() {
+        assert_eq!(call("unknown", &["#ff0000"]), None);
+        assert!(matches!(call("darken", &["#ff0000"]), Some(Err(_))));
+        assert!(matches!(call("hex", &["not a color"]), Some(Err(_))));
+    }
+}
@@ -3,10 +3,49 @@ use atomic_css_organism::css::*;
 use atomic_css_organism::electron::*;
 use atomic_css_organism::molecule::*;
 use atomic_css_organism::organism::*;
+use atomic_css_organism::overlay::*;
+use atomic_css_organism::prop::*;
+use atomic_css_organism::theme::*;
+use atomic_css_organism::token::*;
+use atomic_css_organism::variant::*;
+use atomic_css_parser::lexer::Span;
 use atomic_css_parser::parser::*;
 use derive_more::Display;
 
-type Error = (String, Vec<Node>);
+mod builtins;
+pub mod signatures;
+use builtins::*;
+use signatures::*;
+
+#[derive(Clone, Eq, PartialEq, Debug, Display)]
+pub enum RuntimeError {
+    /// The source is not a valid document.
+    #[display(fmt = "{} at {:?}", _0, _1)]
+    Parse(String, Span),
+    /// A function called with a number of arguments not allowed by its
+    /// signature.
+    #[display(
+        fmt = "`{}` expects {} arguments, got {}, usage: {}",
+        name,
+        expected,
+        found,
+        usage
+    )]
+    Arity {
+        name: String,
+        expected: String,
+        found: usize,
+        usage: String,
+    },
+    /// A `${atom}` placeholder not matching any atom of the molecule.
+    #[display(fmt = "{}", _0)]
+    Placeholder(Box<PlaceholderError>, Vec<Node>),
+    /// Invalid arguments, along with the offending nodes.
+    #[display(fmt = "{}", _0)]
+    Invalid(String, Vec<Node>),
+}
+
+type Error = RuntimeError;
 
 #[derive(Debug, Clone, Display)]
 #[allow(dead_code)]
@@ -14,10 +53,15 @@ enum Value {
     String(String),
     Void,
     Electron(Electron),
-    Molecule(Molecule),
+    Molecule(Box<Molecule>),
     Atom(Atom),
     CSSRule(CSSRule),
     CSSAtRule(CSSAtRule),
+    Token(Token),
+    Theme(Theme),
+    Overlay(Overlay),
+    Condition(Condition),
+    Prop(Prop),
     #[display(fmt = "{:?}", _0)]
     Vec(Vec<Value>),
 }
@@ -34,63 +78,431 @@ impl Runtime {
         }
     }
     pub fn run(&mut self, src: &str) -> Result<(), Error> {
-        let ast = parse(src).expect("Failed to parse file");
+        let ast = parse(src).map_err(|(message, span)| RuntimeError::Parse(message, span))?;
         for node in &ast {
-            if let Node::Function(name, args) = node {
-                self.call_organism_function(name, args)?;
+            if let Node::Function(name, args, span) = node {
+                self.call_organism_function(name, args, span)?;
             }
         }
 
         Ok(())
     }
 
-    fn call_organism_function(&mut self, name: &str, args: &[Node]) -> Result<Value, Error> {
+    fn call_organism_function(
+        &mut self,
+        name: &str,
+        args: &[Node],
+        span: &Span,
+    ) -> Result<Value, Error> {
+        check_arity(name, args)?;
         match name {
-            "electron" => Ok(self.handle_electron(args)?),
+            "electron" => Ok(self.handle_electron(args, span)?),
             "molecule" => Ok(self.handle_molecule(args)?),
+            "token" => Ok(self.handle_token(args)?),
+            "theme" => Ok(self.handle_theme(args)?),
+            "overlay" => Ok(self.handle_overlay(args)?),
+            "breakpoint" | "container" | "supports" => Ok(self.handle_condition(name, args)?),
             _ => Ok(Value::Void),
         }
     }
 
-    fn handle_electron(&mut self, args: &[Node]) -> Result<Value, Error> {
+    fn handle_var(&mut self, args: &[Node], values: &[String]) -> Result<String, Error> {
+        let (token, fallback) = match values {
+            [token] => (token, None),
+            [token, fallback] => (token, Some(fallback)),
+            _ => {
+                return Err(RuntimeError::Invalid(
+                    "Invalid var".to_owned(),
+                    args.to_vec(),
+                ))
+            }
+        };
+        if !self.organism.tokens.contains_key(token) {
+            return Err(RuntimeError::Invalid(
+                format!("Unknown token {}", token),
+                args.to_vec(),
+            ));
+        }
+        Ok(match fallback {
+            Some(fallback) => format!("var({}, {})", get_custom_property(token), fallback),
+            None => format!("var({})", get_custom_property(token)),
+        })
+    }
+
+    fn eval_value(&mut self, node: &Node) -> Result<String, Error> {
+        match node {
+            Node::String(value) => Ok(value.to_owned()),
+            Node::Function(name, args, _) => {
+                let mut values = vec![];
+                for arg in args {
+                    values.push(self.eval_value(arg)?);
+                }
+                check_arity(name, args)?;
+                if name == "var" {
+                    return self.handle_var(args, &values);
+                }
+                match call_builtin(name, &values) {
+                    Some(Ok(value)) => Ok(value),
+                    Some(Err(error)) => Err(RuntimeError::Invalid(
+                        format!("{}: {}", name, error),
+                        args.to_vec(),
+                    )),
+                    None => Err(RuntimeError::Invalid(
+                        format!("Unknown function {}", name),
+                        args.to_vec(),
+                    )),
+                }
+            }
+            Node::Identifier(_) => Err(RuntimeError::Invalid(
+                "Expected value".to_owned(),
+                vec![node.to_owned()],
+            )),
+        }
+    }
+
+    fn parse_electron(&mut self, args: &[Node], span: &Span) -> Result<Electron, Error> {
+        match &args {
+            [Node::String(name), declarations @ ..] if !declarations.is_empty() => {
+                let mut electron = Electron::empty(name).with_span(span.to_owned());
+                for declaration in declarations {
+                    match declaration {
+                        Node::Function(property, val, declaration_span) => {
+                            let value = if let [value] = &val[..] {
+                                self.eval_value(value)?
+                            } else {
+                                return Err(RuntimeError::Invalid(
+                                    "Expected string value".to_owned(),
+                                    args.to_vec(),
+                                ));
+                            };
+                            electron.insert_declaration(
+                                &CSSDeclaration::new(property, &value)
+                                    .with_span(declaration_span.to_owned()),
+                            );
+                        }
+                        _ => {
+                            return Err(RuntimeError::Invalid(
+                                "Invalid electron".to_owned(),
+                                args.to_vec(),
+                            ))
+                        }
+                    }
+                }
+                Ok(electron)
+            }
+            _ => Err(RuntimeError::Invalid(
+                "Invalid electron".to_owned(),
+                args.to_vec(),
+            )),
+        }
+    }
+
+    fn handle_electron(&mut self, args: &[Node], span: &Span) -> Result<Value, Error> {
+        let electron = self.parse_electron(args, span)?;
+        self.organism.insert_electron(&electron);
+        Ok(Value::Electron(electron))
+    }
+
+    fn parse_token(&mut self, args: &[Node]) -> Result<Token, Error> {
         match &args {
-            [Node::String(name), Node::Function(property, val)] => {
-                let value = if let [Node::String(value)] = &val[..1] {
-                    value
-                } else {
-                    return Err(("Expected string value".to_owned(), args.to_vec()));
-                };
-                let electron = Electron::new(name, property, value);
-                self.organism.insert_electron(&electron);
-                Ok(Value::Electron(electron.to_owned()))
+            [Node::String(name), value] => Ok(Token::new(name, &self.eval_value(value)?)),
+            _ => Err(RuntimeError::Invalid(
+                "Invalid token".to_owned(),
+                args.to_vec(),
+            )),
+        }
+    }
+
+    fn handle_token(&mut self, args: &[Node]) -> Result<Value, Error> {
+        let token = self.parse_token(args)?;
+        self.organism.insert_token(&token);
+        Ok(Value::Token(token))
+    }
+
+    fn handle_overlay(&mut self, args: &[Node]) -> Result<Value, Error> {
+        let Some(Node::String(name)) = args.first() else {
+            return Err(RuntimeError::Invalid(
+                "Invalid overlay".to_owned(),
+                args.to_vec(),
+            ));
+        };
+        let mut overlay = Overlay::new(name);
+
+        for node in &args[1..] {
+            if let Node::Function(function, args, _) = node {
+                check_arity(function, args)?;
+            }
+            match node {
+                Node::Function(function, args, span) if function == "electron" => {
+                    overlay.insert_electron(&self.parse_electron(args, span)?);
+                }
+                Node::Function(function, args, _) if function == "token" => {
+                    overlay.insert_token(&self.parse_token(args)?);
+                }
+                _ => {
+                    return Err(RuntimeError::Invalid(
+                        "Invalid overlay".to_owned(),
+                        args.to_vec(),
+                    ))
+                }
+            }
+        }
+
+        self.organism.insert_overlay(&overlay);
+        Ok(Value::Overlay(overlay))
+    }
+
+    fn handle_condition(&mut self, kind: &str, args: &[Node]) -> Result<Value, Error> {
+        let condition = match (kind, &args) {
+            ("breakpoint", [Node::String(name), Node::String(media)]) => {
+                Condition::breakpoint(name, media)
+            }
+            ("container", [Node::String(name), Node::String(query)]) => {
+                Condition::container(name, None, query)
+            }
+            ("container", [Node::String(name), Node::String(container), Node::String(query)]) => {
+                Condition::container(name, Some(container), query)
+            }
+            ("supports", [Node::String(name), Node::String(query)]) => {
+                Condition::supports(name, query)
+            }
+            _ => {
+                return Err(RuntimeError::Invalid(
+                    format!("Invalid {}", kind),
+                    args.to_vec(),
+                ))
+            }
+        };
+        self.organism.insert_condition(&condition);
+        Ok(Value::Condition(condition))
+    }
+
+    fn handle_theme(&mut self, args: &[Node]) -> Result<Value, Error> {
+        let Some(Node::String(name)) = args.first() else {
+            return Err(RuntimeError::Invalid(
+                "Invalid theme".to_owned(),
+                args.to_vec(),
+            ));
+        };
+        let mut theme = Theme::new(name);
+
+        for node in &args[1..] {
+            if let Node::Function(function, args, _) = node {
+                check_arity(function, args)?;
+            }
+            match node {
+                Node::Function(function, args, _) => match (function.as_str(), &args[..]) {
+                    ("selector", [Node::String(selector)]) => theme.insert_selector(selector),
+                    ("media", [Node::String(media)]) => theme.insert_media(media),
+                    ("token", [Node::String(token), _]) => {
+                        if !self.organism.tokens.contains_key(token) {
+                            return Err(RuntimeError::Invalid(
+                                format!("Unknown token {}", token),
+                                args.to_vec(),
+                            ));
+                        }
+                        theme.insert_token(&self.parse_token(args)?);
+                    }
+                    _ => {
+                        return Err(RuntimeError::Invalid(
+                            "Invalid theme".to_owned(),
+                            args.to_vec(),
+                        ))
+                    }
+                },
+                _ => {
+                    return Err(RuntimeError::Invalid(
+                        "Invalid theme".to_owned(),
+                        args.to_vec(),
+                    ))
+                }
             }
-            _ => Err(("Invalid electron".to_owned(), args.to_vec())),
         }
+
+        self.organism.insert_theme(&theme);
+        Ok(Value::Theme(theme))
     }
 
     fn call_molecule_function(
         &mut self,
         name: &str,
         args: &[Node],
+        span: &Span,
         molecule: &mut Molecule,
     ) -> Result<Value, Error> {
+        check_arity(name, args)?;
         match name {
             "atom" => Ok(self.handle_atom(molecule, args)?),
-            "&" => Ok(self.handle_rule(molecule, args)?),
-            "@" => Ok(self.handle_at_rule(molecule, args)?),
+            "&" => Ok(self.handle_rule(molecule, args, span)?),
+            "@" => Ok(self.handle_at_rule(molecule, args, span)?),
+            "prop" => Ok(self.handle_prop(molecule, None, args)?),
+            "compound" => Ok(self.handle_compound(molecule, None, args)?),
             _ => Ok(Value::Void),
         }
     }
 
+    /// Parses the atoms of a prop value or compound. Within an atom the body
+    /// applies to that atom, otherwise it lists `(atom ...)` declarations of
+    /// atoms already in the molecule.
+    fn parse_prop_atoms(
+        &mut self,
+        molecule: &Molecule,
+        atom_name: Option<&str>,
+        nodes: &[Node],
+    ) -> Result<Vec<Atom>, Error> {
+        if let Some(atom_name) = atom_name {
+            let mut atom = Atom::new(atom_name);
+            for node in nodes {
+                if let Node::Function(function, args, _) = node {
+                    self.call_atom_function(function, args, &mut atom)?;
+                }
+            }
+            return Ok(vec![atom]);
+        }
+
+        let mut atoms = vec![];
+        for node in nodes {
+            let Node::Function(function, args, _) = node else {
+                return Err(RuntimeError::Invalid(
+                    "Invalid prop atom".to_owned(),
+                    nodes.to_vec(),
+                ));
+            };
+            match (function.as_str(), args.first()) {
+                ("atom", Some(Node::String(name))) => {
+                    if !molecule.atoms.contains_key(name) {
+                        return Err(RuntimeError::Invalid(
+                            format!("Unknown atom {}", name),
+                            args.to_vec(),
+                        ));
+                    }
+                    atoms.extend(self.parse_prop_atoms(molecule, Some(name), &args[1..])?);
+                }
+                _ => {
+                    return Err(RuntimeError::Invalid(
+                        "Invalid prop atom".to_owned(),
+                        args.to_vec(),
+                    ))
+                }
+            }
+        }
+        Ok(atoms)
+    }
+
+    fn handle_prop(
+        &mut self,
+        molecule: &mut Molecule,
+        atom_name: Option<&str>,
+        args: &[Node],
+    ) -> Result<Value, Error> {
+        let Some(Node::String(name)) = args.first() else {
+            return Err(RuntimeError::Invalid(
+                "Invalid prop".to_owned(),
+                args.to_vec(),
+            ));
+        };
+        let mut prop = Prop::new(name);
+
+        for node in &args[1..] {
+            if let Node::Function(function, args, _) = node {
+                check_arity(function, args)?;
+            }
+            match node {
+                Node::Function(function, args, _) if function == "value" => {
+                    let Some(Node::String(value)) = args.first() else {
+                        return Err(RuntimeError::Invalid(
+                            "Invalid prop value".to_owned(),
+                            args.to_vec(),
+                        ));
+                    };
+                    let mut prop_value = PropValue::new(value);
+                    for atom in self.parse_prop_atoms(molecule, atom_name, &args[1..])? {
+                        prop_value.insert_atom(&atom);
+                    }
+                    prop.insert_value(&prop_value);
+                }
+                _ => {
+                    return Err(RuntimeError::Invalid(
+                        "Invalid prop".to_owned(),
+                        args.to_vec(),
+                    ))
+                }
+            }
+        }
+
+        if prop.values.is_empty() {
+            return Err(RuntimeError::Invalid(
+                format!("Prop {} has no values", name),
+                args.to_vec(),
+            ));
+        }
+        molecule.insert_prop(&prop);
+        Ok(Value::Prop(prop))
+    }
+
+    fn handle_compound(
+        &mut self,
+        molecule: &mut Molecule,
+        atom_name: Option<&str>,
+        args: &[Node],
+    ) -> Result<Value, Error> {
+        let mut compound = CompoundProp::default();
+        let mut body = vec![];
+
+        for node in args {
+            if let Node::Function(function, args, _) = node {
+                check_arity(function, args)?;
+            }
+            match node {
+                Node::Function(function, when, _) if function == "when" => match &when[..] {
+                    [Node::String(prop), Node::String(value)] => {
+                        let exists = molecule
+                            .props
+                            .iter()
+                            .any(|p| p.name == *prop && p.get_value(value).is_some());
+                        if !exists {
+                            return Err(RuntimeError::Invalid(
+                                format!("Unknown prop value {}={}", prop, value),
+                                when.to_vec(),
+                            ));
+                        }
+                        compound.selection.push((prop.to_owned(), value.to_owned()));
+                    }
+                    _ => {
+                        return Err(RuntimeError::Invalid(
+                            "Invalid compound".to_owned(),
+                            when.to_vec(),
+                        ))
+                    }
+                },
+                _ => body.push(node.to_owned()),
+            }
+        }
+
+        if compound.selection.is_empty() {
+            return Err(RuntimeError::Invalid(
+                "Compound without conditions".to_owned(),
+                args.to_vec(),
+            ));
+        }
+        for atom in self.parse_prop_atoms(molecule, atom_name, &body)? {
+            compound.insert_atom(&atom);
+        }
+        molecule.insert_compound(&compound);
+        Ok(Value::Void)
+    }
+
     fn call_atom_function(
         &mut self,
         name: &str,
         args: &[Node],
         atom: &mut Atom,
     ) -> Result<Value, Error> {
+        check_arity(name, args)?;
         match name {
             "electrons" => Ok(self.handle_electrons(atom, args)?),
             "import" => Ok(self.handle_import(atom, args)?),
+            "extends" => Ok(self.handle_extends(atom, args)?),
             _ => Ok(Value::Void),
         }
     }
@@ -100,10 +512,27 @@ impl Runtime {
         for node in args {
             match node {
                 Node::String(name) => {
+                    let reference = match ElectronReference::parse(name) {
+                        Ok(reference) => reference,
+                        Err(error) => return Err(RuntimeError::Invalid(error, args.to_vec())),
+                    };
+                    if let Some(condition) = &reference.condition {
+                        if self.organism.get_condition(condition).is_none() {
+                            return Err(RuntimeError::Invalid(
+                                format!("Unknown condition {}", condition),
+                                args.to_vec(),
+                            ));
+                        }
+                    }
                     atom.insert_electron(name);
                     electrons.push(name)
                 }
-                _ => return Err(("Invalid electron".to_owned(), args.to_vec())),
+                _ => {
+                    return Err(RuntimeError::Invalid(
+                        "Invalid electron".to_owned(),
+                        args.to_vec(),
+                    ))
+                }
             }
         }
         Ok(Value::Vec(
@@ -120,75 +549,143 @@ impl Runtime {
                 atom.insert_import(molecule, imported_atom);
                 Ok(Value::Void)
             }
-            _ => Err(("Invalid import".to_owned(), args.to_vec())),
+            _ => Err(RuntimeError::Invalid(
+                "Invalid import".to_owned(),
+                args.to_vec(),
+            )),
+        }
+    }
+
+    fn handle_extends(&mut self, atom: &mut Atom, args: &[Node]) -> Result<Value, Error> {
+        if args.is_empty() {
+            return Err(RuntimeError::Invalid(
+                "Invalid extends".to_owned(),
+                args.to_vec(),
+            ));
+        }
+        for node in args {
+            match node {
+                Node::String(extended_atom) => atom.insert_extends(extended_atom),
+                _ => {
+                    return Err(RuntimeError::Invalid(
+                        "Invalid extends".to_owned(),
+                        args.to_vec(),
+                    ))
+                }
+            }
         }
+        Ok(Value::Void)
     }
 
     fn handle_atom(&mut self, molecule: &mut Molecule, args: &[Node]) -> Result<Value, Error> {
-        match &args[0] {
-            Node::String(name) => {
-                let mut atom = Atom::new(name);
+        let Some(Node::String(name)) = args.first() else {
+            return Err(RuntimeError::Invalid(
+                "Invalid atom".to_owned(),
+                args.to_vec(),
+            ));
+        };
+        let mut atom = Atom::new(name);
 
-                for node in &args[1..] {
-                    if let Node::Function(name, args) = node {
-                        self.call_atom_function(name, args, &mut atom)?;
-                    }
+        for node in &args[1..] {
+            match node {
+                Node::Function(function, args, _) if function == "prop" => {
+                    check_arity(function, args)?;
+                    self.handle_prop(molecule, Some(name), args)?;
                 }
-
-                molecule.insert_atom(&atom);
-                Ok(Value::Atom(atom.to_owned()))
+                Node::Function(function, args, _) if function == "compound" => {
+                    check_arity(function, args)?;
+                    self.handle_compound(molecule, Some(name), args)?;
+                }
+                Node::Function(function, args, _) => {
+                    self.call_atom_function(function, args, &mut atom)?;
+                }
+                _ => {}
             }
-            _ => Err(("Invalid atom".to_owned(), args.to_vec())),
         }
+
+        molecule.insert_atom(&atom);
+        Ok(Value::Atom(atom.to_owned()))
     }
 
     fn handle_molecule(&mut self, args: &[Node]) -> Result<Value, Error> {
-        match &args[0] {
-            Node::String(name) => {
-                let mut molecule = Molecule::new(name);
-
-                for node in &args[1..] {
-                    if let Node::Function(name, args) = node {
-                        self.call_molecule_function(name, args, &mut molecule)?;
-                    }
-                }
+        let Some(Node::String(name)) = args.first() else {
+            return Err(RuntimeError::Invalid(
+                "Invalid molecule".to_owned(),
+                args.to_vec(),
+            ));
+        };
+        let mut molecule = Molecule::new(name);
 
-                self.organism.insert_molecule(&molecule);
-                Ok(Value::Molecule(molecule.to_owned()))
+        for node in &args[1..] {
+            if let Node::Function(name, args, span) = node {
+                self.call_molecule_function(name, args, span, &mut molecule)?;
             }
-            _ => Err(("Invalid molecule".to_owned(), args.to_vec())),
         }
+
+        // atoms may be declared after the rules using them
+        for node in molecule.get_nodes() {
+            molecule
+                .check_placeholders(node)
+                .map_err(|error| RuntimeError::Placeholder(Box::new(error), args.to_vec()))?;
+        }
+
+        self.organism.insert_molecule(&molecule);
+        Ok(Value::Molecule(Box::new(molecule)))
     }
 
-    fn handle_rule(&mut self, molecule: &mut Molecule, args: &[Node]) -> Result<Value, Error> {
+    fn handle_rule(
+        &mut self,
+        molecule: &mut Molecule,
+        args: &[Node],
+        span: &Span,
+    ) -> Result<Value, Error> {
         let mut css_rule = if let Some(Node::String(selector)) = args.first() {
-            CSSRule::new(selector)
+            let css_rule = CSSRule::new(selector).with_span(span.to_owned());
+            if let Err(error) = css_rule.get_selector_list() {
+                return Err(RuntimeError::Invalid(error, args.to_vec()));
+            }
+            css_rule
         } else {
-            return Err(("Invalid rule".to_owned(), args.to_vec()));
+            return Err(RuntimeError::Invalid(
+                "Invalid rule".to_owned(),
+                args.to_vec(),
+            ));
         };
 
         for node in &args[1..] {
-            if let Node::Function(name, args) = node {
+            if let Node::Function(name, args, span) = node {
+                // declarations, e.g. `container`, may share a name with a function
+                if name == "&" || name == "@" {
+                    check_arity(name, args)?;
+                }
                 match name.as_str() {
                     "@" => {
-                        if let Ok(Value::CSSAtRule(css_at_rule)) =
-                            self.handle_at_rule(&mut Molecule::new("<dummy>"), args)
+                        if let Value::CSSAtRule(css_at_rule) =
+                            self.handle_at_rule(&mut Molecule::new("<dummy>"), args, span)?
                         {
                             css_rule.insert_at_rule(&css_at_rule);
                         }
                     }
                     "&" => {
-                        if let Ok(Value::CSSRule(rule)) =
-                            self.handle_rule(&mut Molecule::new("<dummy>"), args)
+                        if let Value::CSSRule(rule) =
+                            self.handle_rule(&mut Molecule::new("<dummy>"), args, span)?
                         {
                             css_rule.insert_rule(&rule);
                         }
                     }
                     _ => match &args[..] {
-                        [Node::String(value)] => {
-                            css_rule.insert_declaration(&CSSDeclaration::new(name, value));
+                        [value] => {
+                            let value = self.eval_value(value)?;
+                            css_rule.insert_declaration(
+                                &CSSDeclaration::new(name, &value).with_span(span.to_owned()),
+                            );
+                        }
+                        _ => {
+                            return Err(RuntimeError::Invalid(
+                                "Invalid declaration".to_owned(),
+                                args.to_vec(),
+                            ))
                         }
-                        _ => return Err(("Invalid declaration".to_owned(), args.to_vec())),
                     },
                 }
             }
@@ -198,59 +695,71 @@ impl Runtime {
         Ok(Value::CSSRule(css_rule.to_owned()))
     }
 
-    fn handle_at_rule(&mut self, molecule: &mut Molecule, args: &[Node]) -> Result<Value, Error> {
+    fn handle_at_rule(
+        &mut self,
+        molecule: &mut Molecule,
+        args: &[Node],
+        span: &Span,
+    ) -> Result<Value, Error> {
         match &args {
             [Node::String(name)] => {
-                let css_at_rule = CSSAtRule::new(name, None);
+                let css_at_rule = CSSAtRule::new(name, None).with_span(span.to_owned());
                 molecule.insert_css_at_rule(&css_at_rule);
                 Ok(Value::CSSAtRule(css_at_rule.to_owned()))
             }
             [Node::String(name), Node::String(params)] => {
-                let css_at_rule = CSSAtRule::new(name, Some(params));
+                let css_at_rule = CSSAtRule::new(name, Some(params)).with_span(span.to_owned());
                 molecule.insert_css_at_rule(&css_at_rule);
                 Ok(Value::CSSAtRule(css_at_rule.to_owned()))
             }
             [Node::String(name), Node::String(params), rules @ ..] => {
-                let mut css_at_rule = CSSAtRule::new(name, Some(params));
+                // empty parameters let rules follow at-rules without any
+                let params = Some(params.as_str()).filter(|params| !params.is_empty());
+                let mut css_at_rule = CSSAtRule::new(name, params).with_span(span.to_owned());
                 for rule in rules {
                     match rule {
-                        Node::Function(function, args) if function == "&" => {
-                            let css_rule = if let Ok(Value::CSSRule(css_rule)) =
-                                self.handle_rule(&mut Molecule::new("<dummy>"), args)
-                            {
-                                css_rule
-                            } else {
-                                return Err(("Invalid rule".to_owned(), args.to_vec()));
-                            };
-
-                            css_at_rule.insert_rule(&css_rule);
+                        Node::Function(function, args, _) if function == "&" || function == "@" => {
+                            check_arity(function, args)?;
                         }
-                        Node::Function(function, args) if function == "@" => {
-                            let nested_css_at_rule = if let Ok(Value::CSSAtRule(at_rule)) =
-                                self.handle_at_rule(&mut Molecule::new("<dummy>"), args)
+                        _ => {}
+                    }
+                    match rule {
+                        Node::Function(function, args, span) if function == "&" => {
+                            if let Value::CSSRule(css_rule) =
+                                self.handle_rule(&mut Molecule::new("<dummy>"), args, span)?
                             {
-                                at_rule
-                            } else {
-                                return Err(("Invalid at rule".to_owned(), args.to_vec()));
-                            };
-
-                            css_at_rule.insert_at_rule(&nested_css_at_rule);
+                                css_at_rule.insert_rule(&css_rule);
+                            }
                         }
-                        Node::Function(name, args) if args.len() == 1 => {
-                            if let Some(Node::String(value)) = args.first() {
-                                css_at_rule.insert_declaration(&CSSDeclaration::new(name, value));
-                            } else {
-                                return Err(("Invalid declaration".to_owned(), args.to_vec()));
+                        Node::Function(function, args, span) if function == "@" => {
+                            if let Value::CSSAtRule(nested_css_at_rule) =
+                                self.handle_at_rule(&mut Molecule::new("<dummy>"), args, span)?
+                            {
+                                css_at_rule.insert_at_rule(&nested_css_at_rule);
                             }
                         }
-                        _ => return Err(("Invalid rule".to_owned(), args.to_vec())),
+                        Node::Function(name, args, span) if args.len() == 1 => {
+                            let value = self.eval_value(&args[0])?;
+                            css_at_rule.insert_declaration(
+                                &CSSDeclaration::new(name, &value).with_span(span.to_owned()),
+                            );
+                        }
+                        _ => {
+                            return Err(RuntimeError::Invalid(
+                                "Invalid rule".to_owned(),
+                                args.to_vec(),
+                            ))
+                        }
                     }
                 }
 
                 molecule.insert_css_at_rule(&css_at_rule);
                 Ok(Value::CSSAtRule(css_at_rule.to_owned()))
             }
-            _ => Err(("Invalid at rule".to_owned(), args.to_vec())),
+            _ => Err(RuntimeError::Invalid(
+                "Invalid at rule".to_owned(),
+                args.to_vec(),
+            )),
         }
     }
 }
@@ -261,4 +770,540 @@ mod tests {
 
     #[test]
     fn it_works() {}
+
+    #[test]
+    fn it_evaluates_color_functions() {
+        let mut runtime = Runtime::new();
+        runtime
+            .run(
+                r#"
+(electron `red` (color `#ff0000`))
+(electron `red_hover` (color (darken `#ff0000` `10%`)))
+(electron `red_disabled` (color (alpha (mix `#ff0000` `#0000ff` `100%`) `40%`)))
+(molecule `flag`
+  (atom `root` (electrons `red`))
+  (& `${root}` (border-color (hsl (lighten `#ff0000` `10%`)))))
+"#,
+            )
+            .expect("Failed to run program");
+
+        // declarations carry the span of their source, only compare values
+        let get_declarations = |name: &str| -> Vec<(String, String)> {
+            runtime.organism.electrons[name]
+                .declarations
+                .iter()
+                .map(|declaration| {
+                    (
+                        declaration.property.to_owned(),
+                        declaration.value.to_owned(),
+                    )
+                })
+                .collect()
+        };
+        assert_eq!(
+            get_declarations("red_hover"),
+            vec![("color".to_owned(), "#cc0000".to_owned())]
+        );
+        assert_eq!(
+            get_declarations("red_disabled"),
+            vec![("color".to_owned(), "#ff000066".to_owned())]
+        );
+        assert!(runtime.organism.molecules["flag"]
+            .get_css()
+            .contains("border-color:hsl(0 100% 60%);"));
+    }
+
+    #[test]
+    fn it_compiles_token_references_to_custom_properties() {
+        let mut runtime = Runtime::new();
+        runtime
+            .run(
+                r#"
+(token `color.text` `#000000`)
+(token `color.link` `{color.text}`)
+(electron `text` (color (var `color.text`)))
+(electron `link` (color (var `color.link` `blue`)))
+(theme `dark`
+  (selector `[data-theme=dark]`)
+  (media `(prefers-color-scheme: dark)`)
+  (token `color.text` (lighten `#000000` `90%`)))
+"#,
+            )
+            .expect("Failed to run program");
+
+        assert_eq!(
+            runtime.organism.get_css(),
+            ":root{--color-link:var(--color-text);--color-text:#000000;}\
+             [data-theme=dark]{--color-text:#e6e6e6;}\
+             @media (prefers-color-scheme: dark){:root{--color-text:#e6e6e6;}}\
+             .text{color:var(--color-text)}\
+             .link{color:var(--color-link, blue)}"
+        );
+    }
+
+    #[test]
+    fn it_builds_overlays() {
+        let mut runtime = Runtime::new();
+        runtime
+            .run(
+                r#"
+(token `color.brand` `#ff0000`)
+(electron `red` (color `#ff0000`))
+(electron `brand` (color (var `color.brand`)))
+(overlay `acme`
+  (electron `red` (color (darken `#0000ff` `10%`)))
+  (token `color.brand` `#0000ff`))
+"#,
+            )
+            .expect("Failed to run program");
+
+        let overlay = runtime.organism.overlays[0].clone();
+        let acme = runtime
+            .organism
+            .apply_overlay(&overlay)
+            .expect("Failed to apply overlay");
+        assert_eq!(
+            acme.get_css(),
+            ":root{--color-brand:#0000ff;}.red{color:#0000cc}.brand{color:var(--color-brand)}"
+        );
+    }
+
+    #[test]
+    fn it_extends_local_atoms() {
+        let mut runtime = Runtime::new();
+        runtime
+            .run(
+                r#"
+(electron `red` (color `#ff0000`))
+(electron `bold` (font-weight `bold`))
+(molecule `button`
+  (atom `root` (electrons `red`))
+  (atom `label` (extends `root`) (electrons `bold`)))
+"#,
+            )
+            .expect("Failed to run program");
+
+        let exports = runtime.organism.get_exports();
+        assert_eq!(exports["button"]["label"], vec!["red", "bold"]);
+    }
+
+    #[test]
+    fn it_generates_prop_atoms() {
+        let mut runtime = Runtime::new();
+        runtime
+            .run(
+                r#"
+(electron `blue` (color `#0000ff`))
+(electron `red` (color `#ff0000`))
+(electron `large` (font-size `20px`))
+(electron `bold` (font-weight `bold`))
+(molecule `button`
+  (atom `label`)
+  (atom `root`
+    (electrons `blue`)
+    (prop `size` (value `sm`) (value `lg` (electrons `large`))))
+  (prop `tone`
+    (value `primary`)
+    (value `danger` (atom `root` (electrons `red`)) (atom `label` (electrons `bold`))))
+  (compound (when `size` `lg`) (when `tone` `danger`) (atom `label` (electrons `large`)))
+  (& `${root--size-lg}` (padding `1rem`)))
+"#,
+            )
+            .expect("Failed to run program");
+
+        runtime.organism.update_exports().unwrap();
+        let table = runtime.organism.get_prop_table("button");
+        let (selection, atoms) = table.last().unwrap();
+        assert_eq!(
+            selection,
+            &vec![
+                ("size".to_owned(), "lg".to_owned()),
+                ("tone".to_owned(), "danger".to_owned())
+            ]
+        );
+        assert_eq!(
+            atoms["root"],
+            vec!["button_root--size-lg_31", "large", "red"]
+        );
+        assert_eq!(atoms["label"], vec!["bold", "large"]);
+
+        let mut runtime = Runtime::new();
+        assert!(runtime
+            .run("(molecule `button` (atom `root`) (compound (when `size` `lg`)))")
+            .is_err());
+    }
+
+    #[test]
+    fn it_generates_electron_variants() {
+        let mut runtime = Runtime::new();
+        runtime
+            .run(
+                r#"
+(electron `red` (color `#ff0000`))
+(molecule `button`
+  (atom `label` (electrons `red` `hover:red` `group-hover:red`)))
+"#,
+            )
+            .expect("Failed to run program");
+
+        assert_eq!(
+            runtime.organism.get_css(),
+            ".red{color:#ff0000}\
+             .hover\\:red:hover{color:#ff0000}\
+             .group:hover .group-hover\\:red{color:#ff0000}"
+        );
+        assert!(runtime
+            .run("(molecule `button` (atom `label` (electrons `visited:red`)))")
+            .is_err());
+    }
+
+    #[test]
+    fn it_generates_responsive_electrons() {
+        let mut runtime = Runtime::new();
+        runtime
+            .run(
+                r#"
+(breakpoint `md` `(min-width: 768px)`)
+(electron `bg_green` (background-color `#00ff00`))
+(molecule `flag`
+  (atom `root` (electrons `md:bg_green`)))
+"#,
+            )
+            .expect("Failed to run program");
+
+        assert_eq!(
+            runtime.organism.get_css(),
+            ".bg_green{background-color:#00ff00}\
+             @media (min-width: 768px){.md\\:bg_green{background-color:#00ff00;}}"
+        );
+        assert!(runtime
+            .run("(molecule `flag` (atom `root` (electrons `xl:bg_green`)))")
+            .is_err());
+    }
+
+    #[test]
+    fn it_generates_container_and_feature_query_electrons() {
+        let mut runtime = Runtime::new();
+        runtime
+            .run(
+                r#"
+(container `narrow` `(max-width: 300px)`)
+(container `card-md` `card` `(min-width: 400px)`)
+(supports `grid` `(display: grid)`)
+(electron `grid` (display `grid`))
+(molecule `card`
+  (atom `root` (electrons `narrow:grid` `card-md:grid` `grid:grid`)))
+"#,
+            )
+            .expect("Failed to run program");
+
+        assert_eq!(
+            runtime.organism.get_css(),
+            ".grid{display:grid}\
+             @container (max-width: 300px){.narrow\\:grid{display:grid;}}\
+             @container card (min-width: 400px){.card-md\\:grid{display:grid;}}\
+             @supports (display: grid){.grid\\:grid{display:grid;}}"
+        );
+        assert!(runtime.run("(supports `grid`)").is_err());
+    }
+
+    #[test]
+    fn it_parses_multi_declaration_electrons() {
+        let mut runtime = Runtime::new();
+        runtime
+            .run(
+                r#"
+(electron `truncate`
+  (overflow `hidden`)
+  (text-overflow `ellipsis`)
+  (white-space `nowrap`))
+"#,
+            )
+            .expect("Failed to run program");
+
+        assert_eq!(
+            runtime.organism.get_css(),
+            ".truncate{overflow:hidden;text-overflow:ellipsis;white-space:nowrap}"
+        );
+        assert!(runtime.run("(electron `empty`)").is_err());
+        assert!(runtime.run("(electron `red` (color))").is_err());
+        assert!(runtime
+            .run("(electron `red` (color `red`) `blue`)")
+            .is_err());
+    }
+
+    #[test]
+    fn it_rejects_unknown_tokens() {
+        let mut runtime = Runtime::new();
+        assert!(runtime
+            .run("(electron `text` (color (var `color.text`)))")
+            .is_err());
+        assert!(runtime
+            .run("(theme `dark` (token `color.text` `#ffffff`))")
+            .is_err());
+    }
+
+    #[test]
+    fn it_rejects_invalid_color_functions() {
+        let mut runtime = Runtime::new();
+        assert!(runtime
+            .run("(electron `red` (color (darken `nope` `10%`)))")
+            .is_err());
+        assert!(runtime
+            .run("(electron `red` (color (shade `#ff0000`)))")
+            .is_err());
+    }
+
+    #[test]
+    fn it_reports_unknown_placeholders() {
+        let mut runtime = Runtime::new();
+        let error = runtime
+            .run(
+                r#"
+(molecule `flag`
+  (atom `root`)
+  (atom `label`)
+  (& `${rot}:hover` (padding `1rem`)))
+"#,
+            )
+            .unwrap_err();
+        let RuntimeError::Placeholder(error, _) = error else {
+            panic!("Expected a placeholder error, got {:?}", error);
+        };
+        assert_eq!(error.placeholder, "rot");
+        assert_eq!(error.span, Some(53..88));
+        assert_eq!(error.available, vec!["label", "root"]);
+
+        let mut runtime = Runtime::new();
+        runtime
+            .run(
+                r#"
+(molecule `flag`
+  (atom `root`)
+  (& `${root}::before` (content `"\${root}"`)))
+"#,
+            )
+            .expect("Failed to run program");
+        assert_eq!(
+            runtime.organism.molecules["flag"].get_css(),
+            ".flag_root_36::before{content:\"${root}\";}"
+        );
+
+        let mut runtime = Runtime::new();
+        runtime
+            .run("(molecule `flag` (& `${root}` (padding `1rem`)) (atom `root`))")
+            .expect("Failed to run program");
+        assert_eq!(
+            runtime.organism.molecules["flag"].get_css(),
+            ".flag_root_0{padding:1rem;}"
+        );
+    }
+
+    #[test]
+    fn it_keeps_source_spans() {
+        let source = r#"
+(electron `red` (color `#ff0000`))
+(molecule `flag`
+  (atom `root` (electrons `red`))
+  (& `${root}`
+    (padding `1rem`)
+    (@ `media` `print` (display `none`))))
+"#;
+        let mut runtime = Runtime::new();
+        runtime.run(source).expect("Failed to run program");
+        runtime.organism.update_exports().unwrap();
+
+        let output = runtime
+            .organism
+            .write(&atomic_css_organism::serializer::CompactSerializer, true);
+        let mapped: Vec<(&str, &str)> = output
+            .mappings
+            .iter()
+            .map(|(offset, span)| {
+                let css = &output.css[*offset..];
+                let source = &source[span.clone()];
+                (
+                    &css[..css.find(['{', ':']).unwrap()],
+                    source.lines().next().unwrap(),
+                )
+            })
+            .collect();
+        assert_eq!(
+            mapped,
+            vec![
+                (".red", "(electron `red` (color `#ff0000`))"),
+                ("color", "(color `#ff0000`)"),
+                (".flag_root_49", "(& `${root}`"),
+                ("padding", "(padding `1rem`)"),
+                ("@media print", "(@ `media` `print` (display `none`))"),
+                (".flag_root_49", "(& `${root}`"),
+                ("display", "(display `none`)"),
+            ]
+        );
+    }
+
+    #[test]
+    fn it_runs_imported_css() {
+        let import = atomic_css_organism::importer::import_css(
+            r#"
+:root { --brand: red }
+.red { color: var(--brand) }
+.card > a { padding: 1rem }
+@font-face { font-family: Inter }
+"#,
+        )
+        .unwrap();
+        let mut runtime = Runtime::new();
+        runtime
+            .run(&import.get_dsl("legacy"))
+            .expect("Failed to run program");
+        runtime.organism.update_exports().unwrap();
+        assert_eq!(
+            runtime.organism.get_css(),
+            ":root{--brand:red;}.red{color:var(--brand)}.card > a{padding:1rem;}\
+             @font-face{font-family:Inter;}"
+        );
+    }
+
+    #[test]
+    fn it_rejects_invalid_selectors() {
+        let mut runtime = Runtime::new();
+        let error = runtime
+            .run("(molecule `flag` (atom `root`) (& `${root} >` (padding `1rem`)))")
+            .unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "Invalid selector `${root} >`: expected a selector"
+        );
+    }
+
+    #[test]
+    fn it_rejects_invalid_nested_selectors() {
+        let mut runtime = Runtime::new();
+        let error = runtime
+            .run("(molecule `flag` (atom `root`) (& `${root}` (color `red`) (& `a !! b` (color `blue`))))")
+            .unwrap_err();
+        assert!(error.to_string().starts_with("Invalid selector `a !! b`"));
+    }
+
+    #[test]
+    fn it_reports_errors_of_nested_rules() {
+        let mut runtime = Runtime::new();
+        let error = runtime
+            .run("(molecule `flag` (atom `root`) (& `${root}` (& `&:hover` (color (nosuchfn `x`)))))")
+            .unwrap_err();
+        assert_eq!(error.to_string(), "Unknown function nosuchfn");
+
+        let error = runtime
+            .run("(molecule `flag` (atom `root`) (@ `media` `print` (& `${root}` (& `a` (@)))))")
+            .unwrap_err();
+        assert!(matches!(error, RuntimeError::Arity { .. }));
+    }
+
+    #[test]
+    fn it_reports_arity_errors() {
+        let mut runtime = Runtime::new();
+        assert!(matches!(
+            runtime.run("(atom)"),
+            Err(RuntimeError::Arity { found: 0, .. })
+        ));
+        assert!(matches!(
+            runtime.run("(molecule)"),
+            Err(RuntimeError::Arity { found: 0, .. })
+        ));
+        assert!(matches!(
+            runtime.run("(molecule `button` (atom))"),
+            Err(RuntimeError::Arity { found: 0, .. })
+        ));
+        assert!(matches!(
+            runtime.run("(electron `red` (color))"),
+            Err(RuntimeError::Invalid(..))
+        ));
+        assert!(matches!(
+            runtime.run("(molecule `button`"),
+            Err(RuntimeError::Parse(..))
+        ));
+    }
+
+    /// Small xorshift generator, so that the programs are reproducible.
+    struct Rng(u64);
+
+    impl Rng {
+        fn next(&mut self) -> usize {
+            self.0 ^= self.0 << 13;
+            self.0 ^= self.0 >> 7;
+            self.0 ^= self.0 << 17;
+            self.0 as usize
+        }
+
+        fn pick<'a>(&mut self, items: &[&'a str]) -> &'a str {
+            items[self.next() % items.len()]
+        }
+    }
+
+    fn generate_form(rng: &mut Rng, depth: usize, program: &mut String) {
+        let functions: Vec<&str> = SIGNATURES
+            .iter()
+            .map(|signature| signature.name)
+            .chain(["color", "darken", "mix", "unknown"])
+            .collect();
+        let strings = [
+            "`red`",
+            "`#ff0000`",
+            "`10%`",
+            "`hover:red`",
+            "`md:red`",
+            "`md`",
+            "`${root}`",
+            "`root`",
+            "`button`",
+            "`size`",
+            "`lg`",
+            "`color.text`",
+            "``",
+            "`:`",
+        ];
+
+        program.push('(');
+        program.push_str(rng.pick(&functions));
+        for _ in 0..rng.next() % 4 {
+            program.push(' ');
+            match rng.next() % 4 {
+                0 if depth < 4 => generate_form(rng, depth + 1, program),
+                1 => program.push_str(rng.pick(&["identifier", "&", "@"])),
+                _ => program.push_str(rng.pick(&strings)),
+            }
+        }
+        program.push(')');
+    }
+
+    #[test]
+    fn it_never_panics_on_arbitrary_programs() {
+        let mut rng = Rng(0x2545f4914f6cdd1d);
+        let source = include_str!("../../test.lisp");
+
+        for _ in 0..2000 {
+            let mut program = String::new();
+            for _ in 0..rng.next() % 4 + 1 {
+                generate_form(&mut rng, 0, &mut program);
+            }
+            run_and_render(&program);
+
+            // truncated and spliced versions of a valid program
+            let chars: Vec<char> = source.chars().collect();
+            let start = rng.next() % chars.len();
+            let end = start + rng.next() % (chars.len() - start);
+            let mutated: String = chars[..start].iter().chain(&chars[end..]).collect();
+            run_and_render(&mutated);
+        }
+    }
+
+    fn run_and_render(source: &str) {
+        let mut runtime = Runtime::new();
+        if runtime.run(source).is_ok() && runtime.organism.update_exports().is_ok() {
+            let _ = runtime.organism.get_css();
+            let _ = runtime.organism.check_conflicts();
+            let _ = runtime.organism.export_prop_tables();
+        }
+    }
 }
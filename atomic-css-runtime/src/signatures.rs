@@ -0,0 +1,130 @@
+use atomic_css_parser::parser::Node;
+
+use crate::RuntimeError;
+
+/// Number of arguments accepted by a function of the language, nested forms
+/// included.
+#[derive(Clone, Copy, Eq, PartialEq, Debug)]
+pub struct Signature {
+    pub name: &'static str,
+    pub min_args: usize,
+    pub max_args: Option<usize>,
+    pub usage: &'static str,
+}
+
+const fn signature(
+    name: &'static str,
+    min_args: usize,
+    max_args: Option<usize>,
+    usage: &'static str,
+) -> Signature {
+    Signature {
+        name,
+        min_args,
+        max_args,
+        usage,
+    }
+}
+
+pub const SIGNATURES: &[Signature] = &[
+    signature(
+        "electron",
+        2,
+        None,
+        "(electron `name` (property `value`)...)",
+    ),
+    signature("token", 2, Some(2), "(token `name` `value`)"),
+    signature(
+        "theme",
+        1,
+        None,
+        "(theme `name` (selector `selector`) (media `query`) (token `name` `value`)...)",
+    ),
+    signature("selector", 1, Some(1), "(selector `selector`)"),
+    signature("media", 1, Some(1), "(media `query`)"),
+    signature(
+        "overlay",
+        1,
+        None,
+        "(overlay `name` (electron ...) (token ...)...)",
+    ),
+    signature("breakpoint", 2, Some(2), "(breakpoint `name` `query`)"),
+    signature(
+        "container",
+        2,
+        Some(3),
+        "(container `name` [`container`] `query`)",
+    ),
+    signature("supports", 2, Some(2), "(supports `name` `query`)"),
+    signature(
+        "molecule",
+        1,
+        None,
+        "(molecule `name` (atom ...) (& ...) (@ ...)...)",
+    ),
+    signature(
+        "atom",
+        1,
+        None,
+        "(atom `name` (electrons ...) (import ...) (extends ...)...)",
+    ),
+    signature("electrons", 1, None, "(electrons `electron`...)"),
+    signature("import", 2, Some(2), "(import `molecule` `atom`)"),
+    signature("extends", 1, None, "(extends `atom`...)"),
+    signature("prop", 2, None, "(prop `name` (value `value` ...)...)"),
+    signature("value", 1, None, "(value `value` ...)"),
+    signature(
+        "compound",
+        2,
+        None,
+        "(compound (when `prop` `value`)... ...)",
+    ),
+    signature("when", 2, Some(2), "(when `prop` `value`)"),
+    signature("&", 1, None, "(& `selector` (property `value`)...)"),
+    signature("@", 1, None, "(@ `name` [`params`] ...)"),
+    signature("var", 1, Some(2), "(var `token` [`fallback`])"),
+];
+
+pub fn get_signature(name: &str) -> Option<&'static Signature> {
+    SIGNATURES.iter().find(|signature| signature.name == name)
+}
+
+/// Checks the number of arguments of a call against its signature. Functions
+/// without a signature, e.g. declarations, are left to their handler.
+pub fn check_arity(name: &str, args: &[Node]) -> Result<(), RuntimeError> {
+    let Some(signature) = get_signature(name) else {
+        return Ok(());
+    };
+    let too_few = args.len() < signature.min_args;
+    let too_many = signature.max_args.is_some_and(|max| args.len() > max);
+    if too_few || too_many {
+        let expected = match signature.max_args {
+            Some(max) if max == signature.min_args => max.to_string(),
+            Some(max) => format!("{} to {}", signature.min_args, max),
+            None => format!("at least {}", signature.min_args),
+        };
+        return Err(RuntimeError::Arity {
+            name: name.to_owned(),
+            expected,
+            found: args.len(),
+            usage: signature.usage.to_owned(),
+        });
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_checks_arity() {
+        assert!(check_arity("atom", &[]).is_err());
+        assert!(check_arity("import", &[Node::String("button".to_owned())]).is_err());
+        assert!(check_arity("color", &[]).is_ok());
+        assert_eq!(
+            check_arity("token", &[]).unwrap_err().to_string(),
+            "`token` expects 2 arguments, got 0, usage: (token `name` `value`)"
+        );
+    }
+}
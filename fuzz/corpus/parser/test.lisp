@@ -0,0 +1 @@
+../../../test.lisp
\ No newline at end of file
@@ -0,0 +1,9 @@
+#![no_main]
+
+use atomic_css_parser::lexer::Token;
+use libfuzzer_sys::fuzz_target;
+use logos::Logos;
+
+fuzz_target!(|source: &str| {
+    for _ in Token::lexer(source) {}
+});
@@ -0,0 +1,8 @@
+#![no_main]
+
+use atomic_css_parser::parser::parse;
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|source: &str| {
+    let _ = parse(source);
+});
@@ -0,0 +1,28 @@
+#![no_main]
+
+use atomic_css_organism::lint::ContrastLevel;
+use atomic_css_runtime::Runtime;
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|source: &str| {
+    let mut runtime = Runtime::new();
+    if runtime.run(source).is_err() {
+        return;
+    }
+
+    // whatever the program, the organism must render without panicking
+    let organism = &mut runtime.organism;
+    if organism.update_exports().is_err() {
+        return;
+    }
+    let _ = organism.get_css();
+    let _ = organism.check_contrast(ContrastLevel::AA);
+    let _ = organism.check_conflicts();
+    let _ = organism.export_prop_tables();
+    let _ = organism.export_dtcg();
+    for overlay in organism.overlays.clone() {
+        if let Ok(organism) = organism.apply_overlay(&overlay) {
+            let _ = organism.get_css();
+        }
+    }
+});
@@ -1,12 +1,33 @@
+(breakpoint `md` `(min-width: 768px)`)
+(breakpoint `lg` `(min-width: 1024px)`)
+(container `card-md` `card` `(min-width: 400px)`)
+(supports `grid` `(display: grid)`)
+
 (electron `red` (color `#ff0000`))
 (electron `blue` (color `#0000ff`))
+(electron `red_hover` (color (darken `#ff0000` `10%`)))
 (electron `bg_green` (background-color `#00ff00`))
+(electron `truncate`
+  (overflow `hidden`)
+  (text-overflow `ellipsis`)
+  (white-space `nowrap`))
+
+(token `color.text` `#000000`)
+(electron `text` (color (var `color.text`)))
+(theme `dark`
+  (selector `[data-theme=dark]`)
+  (media `(prefers-color-scheme: dark)`)
+  (token `color.text` `#ffffff`))
 
 (molecule `button`
-  (atom `label` (electrons `blue`)))
+  (atom `label` (electrons `blue` `md:red`))
+  (atom `icon` (extends `label`) (electrons `truncate`))
+  (prop `tone`
+    (value `primary`)
+    (value `danger` (atom `label` (electrons `red`)))))
 
 (molecule `flag`
-  (atom `root` (electrons `red` `bg_green`))
+  (atom `root` (electrons `red` `bg_green` `hover:red_hover`))
   (atom `label` (import `button` `label`))
   (& `${root}`
     (padding `1rem`)
@@ -14,8 +35,13 @@
       (background-color `#ff00ff`))
     (margin `0`)
     (@ `media` `print` (display `none`)))
+  (& `${root}:hover ${button.label}` (text-decoration `underline`))
   (@ `foo`)
   (@ `bar` `baz`)
   (@ `media` `(min-width: 1024px)`
     (& `${root}` (padding `1.5rem`))))
 
+
+(overlay `acme`
+  (electron `red` (color `#e60000`))
+  (token `color.text` `#1a1a1a`))